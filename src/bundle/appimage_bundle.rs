@@ -0,0 +1,237 @@
+// The structure of an AppImage looks something like this:
+//
+// FooBar-1.2.3-x86_64.AppImage
+//     runtime         # ELF executable that mounts the image and runs AppRun
+//     squashfs image  # Contains the AppDir:
+//         AppRun                                      # Script that runs the binary
+//         foobar.desktop                              # Copy of the desktop file
+//         foobar.png                                  # Copy of the largest icon
+//         .DirIcon                                    # Another copy of that icon
+//         usr/bin/foobar                              # Binary executable file
+//         usr/share/applications/foobar.desktop       # Desktop file (for apps)
+//         usr/share/icons/hicolor/...                 # Icon files (for apps)
+//         usr/lib/foobar/...                          # Other resource files
+//
+// The files under usr/ are laid out exactly as for the deb bundle.  The
+// runtime isn't generated by cargo-bundle; it must be supplied with the
+// `appimage_runtime` setting (see
+// https://github.com/AppImage/type2-runtime/releases for prebuilt runtimes).
+// The SquashFS image is written directly, so that no external tools are
+// needed.  For more information about the format, see
+// https://github.com/AppImage/AppImageSpec/blob/master/draft.md.
+
+use super::common;
+use super::deb_bundle;
+use super::squashfs;
+use {PackageType, ResultExt, Settings};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// The magic number at the start of every ELF file.
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
+    let arch = match settings.binary_arch() {
+        "x86" => "i686",
+        "arm" => "armhf",
+        other => other,
+    };
+    let runtime = read_runtime(settings)?;
+    let name = settings.bundle_name().replace(' ', "-");
+    let package_base_name = format!("{}-{}-{}", name, settings.version_string(), arch);
+    let package_name = format!("{}.AppImage", package_base_name);
+    common::print_bundling(&package_name)?;
+    let base_dir = settings.project_out_directory().join("bundle/appimage");
+    let package_dir = base_dir.join(&package_base_name);
+    if package_dir.exists() {
+        fs::remove_dir_all(&package_dir).chain_err(|| {
+            format!("Failed to remove old {}", package_base_name)
+        })?;
+    }
+    let package_path = base_dir.join(package_name);
+
+    // Generate the AppDir, starting from the same files as a deb package.
+    let app_dir = deb_bundle::generate_data(settings, PackageType::AppImage, &package_dir)?;
+    generate_top_level_files(settings.binary_name(), &app_dir)?;
+    write_app_image(&runtime, &app_dir, &package_path, settings.source_date_epoch())?;
+    Ok(vec![package_path])
+}
+
+/// Reads the AppImage runtime from the path given in the settings, checking
+/// that it's an ELF executable.
+fn read_runtime(settings: &Settings) -> ::Result<Vec<u8>> {
+    let path = match settings.appimage_runtime() {
+        Some(path) => path,
+        None => bail!("The appimage_runtime setting is required to build an AppImage (prebuilt \
+                       runtimes are available from \
+                       https://github.com/AppImage/type2-runtime/releases)"),
+    };
+    let mut runtime = Vec::new();
+    File::open(path).and_then(|mut file| file.read_to_end(&mut runtime)).chain_err(|| {
+        format!("Failed to read AppImage runtime {:?}", path)
+    })?;
+    if !runtime.starts_with(ELF_MAGIC) {
+        bail!("AppImage runtime {:?} is not an ELF executable", path);
+    }
+    Ok(runtime)
+}
+
+/// Generate the files that go at the top of the AppDir (the AppRun script,
+/// desktop file and icon), given the usr/ tree from the deb package.
+fn generate_top_level_files(bin_name: &str, app_dir: &Path) -> ::Result<()> {
+    generate_app_run(bin_name, app_dir).chain_err(|| "Failed to create AppRun script")?;
+    let desktop_file_name = format!("{}.desktop", bin_name);
+    common::copy_file(&app_dir.join("usr/share/applications").join(&desktop_file_name),
+                      &app_dir.join(&desktop_file_name)).chain_err(|| {
+        "Failed to copy desktop file"
+    })?;
+    copy_top_level_icon(bin_name, app_dir).chain_err(|| "Failed to copy icon file")?;
+    Ok(())
+}
+
+/// Write the AppImage file: the runtime, followed by the SquashFS image of
+/// the AppDir.
+fn write_app_image(runtime: &[u8], app_dir: &Path, package_path: &Path,
+                   source_date_epoch: Option<u64>) -> ::Result<()> {
+    let mut file = common::create_file(package_path)?;
+    file.write_all(runtime)?;
+    let is_executable = |path: &Path, metadata: &fs::Metadata| {
+        path == Path::new("AppRun") || path.starts_with("usr/bin") ||
+            common::is_executable(metadata)
+    };
+    squashfs::write_squashfs(app_dir, &mut file, is_executable, source_date_epoch)
+        .chain_err(|| "Failed to create SquashFS image")?;
+    file.flush()?;
+    drop(file);
+    set_executable(package_path)
+}
+
+/// Generate the AppRun script, which runs the binary from wherever the
+/// AppImage has been mounted.
+fn generate_app_run(bin_name: &str, app_dir: &Path) -> ::Result<()> {
+    let app_run_path = app_dir.join("AppRun");
+    let mut file = common::create_file(&app_run_path)?;
+    writeln!(file, "#!/bin/sh")?;
+    writeln!(file, "HERE=\"$(dirname \"$(readlink -f \"$0\")\")\"")?;
+    writeln!(file, "exec \"$HERE/usr/bin/{}\" \"$@\"", bin_name)?;
+    file.flush()?;
+    drop(file);
+    set_executable(&app_run_path)
+}
+
+/// Copy the largest (standard density) icon generated under usr/share/icons
+/// to the top of the AppDir, both as `<binary name>.png` (which the desktop
+/// file's `Icon` entry refers to) and as `.DirIcon`.
+fn copy_top_level_icon(bin_name: &str, app_dir: &Path) -> ::Result<()> {
+    let icon_name = format!("{}.png", bin_name);
+    let icons_dir = app_dir.join("usr/share/icons/hicolor");
+    let mut largest: Option<(u32, PathBuf)> = None;
+    if icons_dir.is_dir() {
+        for entry in fs::read_dir(&icons_dir)? {
+            let entry = entry?;
+            let size_name = entry.file_name().to_string_lossy().into_owned();
+            if size_name.ends_with("@2x") {
+                continue;
+            }
+            let width = match size_name.split('x').next().and_then(|w| w.parse::<u32>().ok()) {
+                Some(width) => width,
+                None => continue,
+            };
+            let path = entry.path().join("apps").join(&icon_name);
+            if path.is_file() && largest.as_ref().is_none_or(|&(w, _)| width > w) {
+                largest = Some((width, path));
+            }
+        }
+    }
+    match largest {
+        Some((_, path)) => {
+            common::copy_file(&path, &app_dir.join(&icon_name))?;
+            common::copy_file(&path, &app_dir.join(".DirIcon"))?;
+        }
+        None => {
+            common::print_warning("No PNG icon is available for the AppImage, so it will \
+                                   appear without an icon")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> ::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> ::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_top_level_files, write_app_image};
+    use std::fs;
+    use std::path::Path;
+    use tempfile;
+
+    #[cfg(unix)]
+    fn is_executable(path: &Path) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).unwrap().permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(not(unix))]
+    fn is_executable(_path: &Path) -> bool {
+        true
+    }
+
+    // Writes a file (creating its parent directories) under the AppDir.
+    fn write_file(app_dir: &Path, path: &str, contents: &str) {
+        let path = app_dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn top_level_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let app_dir = tmp.path().join("AppDir");
+        write_file(&app_dir, "usr/bin/example", "binary");
+        write_file(&app_dir, "usr/share/applications/example.desktop", "[Desktop Entry]\n");
+        let icons = "usr/share/icons/hicolor";
+        write_file(&app_dir, &format!("{}/32x32/apps/example.png", icons), "32");
+        write_file(&app_dir, &format!("{}/128x128/apps/example.png", icons), "128");
+        write_file(&app_dir, &format!("{}/256x256@2x/apps/example.png", icons), "256@2x");
+        write_file(&app_dir, &format!("{}/512x512/apps/other.png", icons), "other");
+        generate_top_level_files("example", &app_dir).unwrap();
+
+        let app_run = app_dir.join("AppRun");
+        assert_eq!(fs::read_to_string(&app_run).unwrap(),
+                   "#!/bin/sh\n\
+                    HERE=\"$(dirname \"$(readlink -f \"$0\")\")\"\n\
+                    exec \"$HERE/usr/bin/example\" \"$@\"\n");
+        assert!(is_executable(&app_run));
+        assert_eq!(fs::read_to_string(app_dir.join("example.desktop")).unwrap(),
+                   "[Desktop Entry]\n");
+        // The largest standard density icon for this binary is the one used.
+        assert_eq!(fs::read_to_string(app_dir.join("example.png")).unwrap(), "128");
+        assert_eq!(fs::read_to_string(app_dir.join(".DirIcon")).unwrap(), "128");
+    }
+
+    #[test]
+    fn runtime_precedes_image() {
+        let tmp = tempfile::tempdir().unwrap();
+        let app_dir = tmp.path().join("AppDir");
+        write_file(&app_dir, "AppRun", "#!/bin/sh\n");
+        write_file(&app_dir, "usr/bin/example", "binary");
+        let runtime = b"\x7fELF runtime";
+        let package_path = tmp.path().join("Example.AppImage");
+        write_app_image(runtime, &app_dir, &package_path, Some(0)).unwrap();
+        let data = fs::read(&package_path).unwrap();
+        assert!(data.starts_with(runtime));
+        assert_eq!(&data[runtime.len()..runtime.len() + 4], b"hsqs");
+        assert!(is_executable(&package_path));
+    }
+}
@@ -0,0 +1,509 @@
+// The structure of a portable archive looks something like this:
+//
+// foobar-1.2.3-x86_64-unknown-linux-gnu.tar.gz   # Or .zip, for Windows targets
+//     foobar-1.2.3-x86_64-unknown-linux-gnu/
+//         foobar                # Binary executable file (foobar.exe on Windows)
+//         README.md             # Readme and license files from the project
+//         LICENSE               #   directory, if any
+//         icons/...             # The bundle's icon files
+//         resources/...         # Resource files
+//
+// The archive is meant to be unpacked anywhere and run in place, so nothing
+// is installed or generated; files are added exactly as they are, except
+// that everything is owned by root, the binary and any files that are
+// already executable get 0755 permissions (and all other files 0644), and
+// symbolic links are stored as links rather than as the files they point to.
+// Links with absolute targets, or whose targets lie outside the part of the
+// archive they were collected into (such as resources/), would dangle once
+// the archive is unpacked elsewhere, so the files they point to are stored
+// instead.
+// Tar archives are gzip-compressed by default, but can instead use any of
+// the compression formats supported for deb packages.
+
+use super::common;
+use super::deb_bundle::CompressedWriter;
+use {PackageType, ResultExt, Settings};
+use chrono::{self, Datelike, TimeZone, Timelike};
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tar;
+use zip;
+
+// The prefixes (in upper case) of the names of the readme and license files
+// that are included from the project directory.
+const DOC_FILE_PREFIXES: &[&str] = &["README", "LICENSE", "LICENCE", "COPYING"];
+
+pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
+    let is_windows = settings.binary_os() == "windows";
+    let triple = target_triple(settings)?;
+    let top_dir = format!("{}-{}-{}", settings.binary_name(), settings.version_string(),
+                          triple);
+    let archive_name = if is_windows {
+        format!("{}.zip", top_dir)
+    } else {
+        format!("{}.{}", top_dir, settings.archive_compression().tar_extension())
+    };
+    common::print_bundling(&archive_name)?;
+    let archive_path = settings.project_out_directory().join("bundle/archive").join(archive_name);
+
+    let entries = collect_entries(settings, Path::new(&top_dir), is_windows).chain_err(|| {
+        "Failed to collect archive contents"
+    })?;
+    let file = common::create_file(&archive_path)?;
+    let mut writer = if is_windows {
+        ArchiveWriter::Zip(zip::ZipWriter::new(file))
+    } else {
+        let compression = settings.archive_compression();
+        let (_, _, level) = compression.levels();
+        let encoder = CompressedWriter::new(file, compression, level,
+                                            settings.source_date_epoch())?;
+        ArchiveWriter::Tar(tar::Builder::new(encoder))
+    };
+    write_entries(&mut writer, &entries, settings.source_date_epoch()).chain_err(|| {
+        "Failed to create archive"
+    })?;
+    writer.finish()?.flush()?;
+    Ok(vec![archive_path])
+}
+
+// Returns the target triple that the binary was built for: the one given
+// with `--target`, or else the host's, as reported by rustc.
+fn target_triple(settings: &Settings) -> ::Result<String> {
+    if let Some(triple) = settings.target_triple() {
+        return Ok(triple.to_string());
+    }
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc"));
+    let output = Command::new(rustc).arg("-vV").output().chain_err(|| {
+        "Failed to run `rustc -vV` to find the host target triple"
+    })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in stdout.lines() {
+        if let Some(host) = line.strip_prefix("host: ") {
+            return Ok(host.trim().to_string());
+        }
+    }
+    bail!("Failed to find the host target triple in the output of `rustc -vV`");
+}
+
+// A file, directory or symbolic link in the archive.
+#[derive(Debug, PartialEq)]
+enum Entry {
+    Directory,
+    // A regular file, with the path to copy it from and its permission bits.
+    File(PathBuf, u32),
+    // A symbolic link, with the path it points to.
+    Symlink(PathBuf),
+}
+
+// Returns the entries of the archive, keyed by their paths within it.  Since
+// paths are ordered component by component, each directory comes before its
+// contents.
+fn collect_entries(settings: &Settings, top_dir: &Path, is_windows: bool)
+                   -> ::Result<BTreeMap<PathBuf, Entry>> {
+    let mut entries = BTreeMap::new();
+    // Windows binaries have an .exe extension, which the binary name lacks.
+    let mut binary_name = OsString::from(settings.binary_name());
+    if is_windows {
+        binary_name.push(".exe");
+    }
+    let binary_path = settings.binary_path().with_file_name(&binary_name);
+    if !binary_path.is_file() {
+        bail!("{:?} does not exist", binary_path);
+    }
+    entries.insert(top_dir.join(&binary_name), Entry::File(binary_path, 0o755));
+    for path in doc_files(settings)? {
+        let dest = top_dir.join(path.file_name().unwrap());
+        add_entry(&mut entries, top_dir, dest, &path)?;
+    }
+    let icons_dir = top_dir.join("icons");
+    for path in settings.icon_files() {
+        let path = path?;
+        let dest = icons_dir.join(path.file_name().unwrap());
+        add_entry(&mut entries, &icons_dir, dest, &path)?;
+    }
+    let resources_dir = top_dir.join("resources");
+    for (src, dest) in settings.resource_files(PackageType::Archive)? {
+        add_entry(&mut entries, &resources_dir, resources_dir.join(dest), &src)?;
+    }
+    add_directories(&mut entries);
+    Ok(entries)
+}
+
+// Returns the readme and license files in the project directory, along with
+// the `license_file` from the bundle settings (if any).
+fn doc_files(settings: &Settings) -> ::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(".")? {
+        let path = PathBuf::from(entry?.file_name());
+        let name = path.to_string_lossy().to_uppercase();
+        if path.is_file() && DOC_FILE_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    if let Some(license_file) = settings.license_file() {
+        let license_file: PathBuf = license_file.components()
+            .filter(|component| *component != Component::CurDir)
+            .collect();
+        if !files.contains(&license_file) {
+            files.push(license_file);
+        }
+    }
+    Ok(files)
+}
+
+// Adds an entry for the file or symbolic link at `src` to the archive at
+// `dest`, within the archive directory `root`, failing if a different file is
+// already there.  A symbolic link whose target isn't within `root` is replaced
+// by the file it points to.
+fn add_entry(entries: &mut BTreeMap<PathBuf, Entry>, root: &Path, dest: PathBuf, src: &Path)
+             -> ::Result<()> {
+    let mut metadata = fs::symlink_metadata(src).chain_err(|| {
+        format!("Failed to read {:?}", src)
+    })?;
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(src)?;
+        if link_target_within(root, &dest, &target) {
+            return insert_entry(entries, dest, Entry::Symlink(target));
+        }
+        metadata = fs::metadata(src).chain_err(|| {
+            format!("Failed to read {:?}, the target of {:?}", target, src)
+        })?;
+    }
+    let entry = if metadata.is_file() {
+        Entry::File(src.to_path_buf(), if common::is_executable(&metadata) { 0o755 } else { 0o644 })
+    } else {
+        bail!("{:?} is not a file", src);
+    };
+    insert_entry(entries, dest, entry)
+}
+
+// Adds `entry` to the archive at `dest`, failing if a different entry is
+// already there.
+fn insert_entry(entries: &mut BTreeMap<PathBuf, Entry>, dest: PathBuf, entry: Entry)
+                -> ::Result<()> {
+    match entries.get(&dest) {
+        Some(existing) if *existing == entry => return Ok(()),
+        Some(_) => bail!("More than one file would be placed at {:?} in the archive", dest),
+        None => {}
+    }
+    entries.insert(dest, entry);
+    Ok(())
+}
+
+// Returns true if a symbolic link at `dest` pointing to `target` resolves to
+// a path within `root`, without passing outside of it along the way.
+fn link_target_within(root: &Path, dest: &Path, target: &Path) -> bool {
+    let mut resolved = match dest.parent() {
+        Some(parent) => parent.to_path_buf(),
+        None => return false,
+    };
+    for component in target.components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() || !resolved.starts_with(root) {
+                    return false;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    resolved.starts_with(root)
+}
+
+// Adds entries for the parent directories of every entry.
+fn add_directories(entries: &mut BTreeMap<PathBuf, Entry>) {
+    let mut directories = Vec::new();
+    for path in entries.keys() {
+        for ancestor in path.ancestors().skip(1) {
+            if !ancestor.as_os_str().is_empty() {
+                directories.push(ancestor.to_path_buf());
+            }
+        }
+    }
+    for directory in directories {
+        entries.insert(directory, Entry::Directory);
+    }
+}
+
+// Writes the entries to the archive, with modification times clamped to the
+// `source_date_epoch` (if any).  Directories are given the time of the build.
+fn write_entries<W: Write + Seek>(writer: &mut ArchiveWriter<W>,
+                                  entries: &BTreeMap<PathBuf, Entry>,
+                                  source_date_epoch: Option<u64>)
+                                  -> ::Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let clamp = |mtime: u64| source_date_epoch.map_or(mtime, |epoch| min(mtime, epoch));
+    for (path, entry) in entries {
+        match *entry {
+            Entry::Directory => writer.append_directory(path, clamp(now))?,
+            Entry::File(ref src, mode) => {
+                let mtime = clamp(common::modified_time(&fs::metadata(src)?));
+                writer.append_file(path, src, mode, mtime).chain_err(|| {
+                    format!("Failed to add {:?} to the archive", src)
+                })?;
+            }
+            Entry::Symlink(ref target) => writer.append_symlink(path, target, clamp(now))?,
+        }
+    }
+    Ok(())
+}
+
+// Writes entries to either a (compressed) tar archive or a zip archive.
+enum ArchiveWriter<W: Write + Seek> {
+    Tar(tar::Builder<CompressedWriter<W>>),
+    Zip(zip::ZipWriter<W>),
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    fn append_directory(&mut self, path: &Path, mtime: u64) -> ::Result<()> {
+        match *self {
+            ArchiveWriter::Tar(ref mut builder) => {
+                let mut header = tar_header(tar::EntryType::Directory, 0o755, mtime)?;
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+            ArchiveWriter::Zip(ref mut writer) => {
+                writer.add_directory(zip_path(path)?, zip_options(0o755, mtime))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn append_file(&mut self, path: &Path, src: &Path, mode: u32, mtime: u64) -> ::Result<()> {
+        let mut file = File::open(src)?;
+        match *self {
+            ArchiveWriter::Tar(ref mut builder) => {
+                let mut header = tar_header(tar::EntryType::Regular, mode, mtime)?;
+                header.set_size(file.metadata()?.len());
+                builder.append_data(&mut header, path, &mut file)?;
+            }
+            ArchiveWriter::Zip(ref mut writer) => {
+                writer.start_file(zip_path(path)?, zip_options(mode, mtime))?;
+                io::copy(&mut file, writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn append_symlink(&mut self, path: &Path, target: &Path, mtime: u64) -> ::Result<()> {
+        match *self {
+            ArchiveWriter::Tar(ref mut builder) => {
+                let mut header = tar_header(tar::EntryType::Symlink, 0o777, mtime)?;
+                header.set_link_name(target)?;
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+            ArchiveWriter::Zip(ref mut writer) => {
+                writer.add_symlink(zip_path(path)?, zip_path(target)?,
+                                   zip_options(0o777, mtime))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Finishes the archive and returns the underlying writer.
+    fn finish(self) -> ::Result<W> {
+        Ok(match self {
+            ArchiveWriter::Tar(builder) => builder.into_inner()?.finish()?,
+            ArchiveWriter::Zip(mut writer) => writer.finish()?,
+        })
+    }
+}
+
+// Returns a tar header for an entry owned by root:root.
+fn tar_header(entry_type: tar::EntryType, mode: u32, mtime: u64) -> ::Result<tar::Header> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_username("root")?;
+    header.set_groupname("root")?;
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_size(0);
+    Ok(header)
+}
+
+// Returns the options for a zip entry.  Zip timestamps can only represent
+// (local) times from 1980 to 2107, in two-second steps; times outside that
+// range are stored as the earliest possible time.
+fn zip_options(mode: u32, mtime: u64) -> zip::write::FileOptions {
+    let datetime = chrono::Utc.timestamp_opt(mtime as i64, 0).single()
+        .and_then(|datetime| {
+            zip::DateTime::from_date_and_time(u16::try_from(datetime.year()).ok()?,
+                                              datetime.month() as u8, datetime.day() as u8,
+                                              datetime.hour() as u8, datetime.minute() as u8,
+                                              datetime.second() as u8).ok()
+        })
+        .unwrap_or_default();
+    zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .unix_permissions(mode)
+        .last_modified_time(datetime)
+}
+
+// Returns a path in the form used for zip entry names, with `/` separators.
+fn zip_path(path: &Path) -> ::Result<String> {
+    let components: Option<Vec<&str>> = path.components().map(|component| {
+        match component {
+            Component::Normal(name) => name.to_str(),
+            Component::ParentDir => Some(".."),
+            _ => None,
+        }
+    }).collect();
+    match components {
+        Some(components) => Ok(components.join("/")),
+        None => bail!("Can't store {:?} in a zip archive", path),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{ArchiveWriter, Entry, add_directories, add_entry, link_target_within,
+                write_entries};
+    use bundle::deb_bundle::CompressedWriter;
+    use bundle::settings::DebCompression;
+    use flate2::read::GzDecoder;
+    use std::collections::BTreeMap;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::os::unix;
+    use std::path::{Path, PathBuf};
+    use tar;
+    use tempfile;
+    use zip;
+
+    // Collects the entries for a binary, a resource file, and a symbolic
+    // link to it, under a top-level `app` directory.  Two more links to the
+    // resource file, one absolute and one reaching outside of the resources
+    // directory, are stored as copies of it.
+    fn test_entries(dir: &Path) -> BTreeMap<PathBuf, Entry> {
+        fs::write(dir.join("app"), b"binary").unwrap();
+        fs::write(dir.join("data.txt"), b"data").unwrap();
+        unix::fs::symlink("data.txt", dir.join("link.txt")).unwrap();
+        unix::fs::symlink(dir.join("data.txt"), dir.join("absolute.txt")).unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        unix::fs::symlink("../data.txt", dir.join("sub/outside.txt")).unwrap();
+        let mut entries = BTreeMap::new();
+        entries.insert(PathBuf::from("app/app"), Entry::File(dir.join("app"), 0o755));
+        let root = Path::new("app/resources");
+        for name in &["data.txt", "link.txt", "absolute.txt", "sub/outside.txt"] {
+            let dest = root.join(Path::new(name).file_name().unwrap());
+            add_entry(&mut entries, root, dest.clone(), &dir.join(name)).unwrap();
+            add_entry(&mut entries, root, dest, &dir.join(name)).unwrap();
+        }
+        assert!(add_entry(&mut entries, root, PathBuf::from("app/resources/data.txt"),
+                          &dir.join("app")).is_err());
+        add_directories(&mut entries);
+        entries
+    }
+
+    #[test]
+    fn tar_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = test_entries(dir.path());
+        let path = dir.path().join("app.tar.gz");
+        let encoder = CompressedWriter::new(File::create(&path).unwrap(), DebCompression::Gzip,
+                                            9, Some(0)).unwrap();
+        let mut writer = ArchiveWriter::Tar(tar::Builder::new(encoder));
+        write_entries(&mut writer, &entries, Some(0)).unwrap();
+        writer.finish().unwrap();
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(&path).unwrap()));
+        let mut found = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let header = entry.header().clone();
+            assert_eq!(header.mtime().unwrap(), 0);
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            let link = header.link_name().unwrap().map(|link| link.to_string_lossy().into_owned());
+            found.push((path, header.mode().unwrap(), contents, link));
+        }
+        let entry = |path: &str, mode: u32, contents: &str, link: Option<&str>| {
+            (path.to_string(), mode, contents.to_string(), link.map(str::to_string))
+        };
+        assert_eq!(found, vec![
+            entry("app", 0o755, "", None),
+            entry("app/app", 0o755, "binary", None),
+            entry("app/resources", 0o755, "", None),
+            entry("app/resources/absolute.txt", 0o644, "data", None),
+            entry("app/resources/data.txt", 0o644, "data", None),
+            entry("app/resources/link.txt", 0o777, "", Some("data.txt")),
+            entry("app/resources/outside.txt", 0o644, "data", None),
+        ]);
+    }
+
+    #[test]
+    fn zip_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = test_entries(dir.path());
+        let path = dir.path().join("app.zip");
+        let mut writer = ArchiveWriter::Zip(zip::ZipWriter::new(File::create(&path).unwrap()));
+        write_entries(&mut writer, &entries, Some(0)).unwrap();
+        writer.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut found = Vec::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).unwrap();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            found.push((file.name().to_string(), file.unix_mode().unwrap(), contents));
+        }
+        let entry = |path: &str, mode: u32, contents: &str| {
+            (path.to_string(), mode, contents.to_string())
+        };
+        assert_eq!(found, vec![
+            entry("app/", 0o40755, ""),
+            entry("app/app", 0o100755, "binary"),
+            entry("app/resources/", 0o40755, ""),
+            entry("app/resources/absolute.txt", 0o100644, "data"),
+            entry("app/resources/data.txt", 0o100644, "data"),
+            entry("app/resources/link.txt", 0o120777, "data.txt"),
+            entry("app/resources/outside.txt", 0o100644, "data"),
+        ]);
+    }
+
+    #[test]
+    fn link_targets() {
+        let within = |dest, target| {
+            link_target_within(Path::new("app/resources"), Path::new(dest), Path::new(target))
+        };
+        assert!(within("app/resources/a.txt", "b.txt"));
+        assert!(within("app/resources/img/a.png", "../b.png"));
+        assert!(within("app/resources/img/a.png", "./c/../b.png"));
+        assert!(!within("app/resources/a.txt", "/etc/passwd"));
+        assert!(!within("app/resources/a.txt", "../a.txt"));
+        assert!(!within("app/resources/a.txt", "../resources/b.txt"));
+        assert!(!within("app/resources/img/a.png", "../../../../b.png"));
+    }
+
+    #[test]
+    fn zip_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app"), b"binary").unwrap();
+        let mut entries = BTreeMap::new();
+        entries.insert(PathBuf::from("app"), Entry::File(dir.path().join("app"), 0o755));
+        let path = dir.path().join("app.zip");
+        let mut writer = ArchiveWriter::Zip(zip::ZipWriter::new(File::create(&path).unwrap()));
+        // 2023-11-14 22:13:20 UTC.
+        write_entries(&mut writer, &entries, Some(1700000000)).unwrap();
+        writer.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let time = archive.by_index(0).unwrap().last_modified();
+        assert_eq!((time.year(), time.month(), time.day()), (2023, 11, 14));
+        assert_eq!((time.hour(), time.minute(), time.second()), (22, 13, 20));
+    }
+}
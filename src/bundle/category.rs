@@ -175,6 +175,53 @@ impl AppCategory {
         }
     }
 
+    /// Map an AppCategory to the closest Debian archive section that matches
+    /// that category.
+    pub fn debian_section(&self) -> &'static str {
+        match &self {
+            AppCategory::Business => "misc",
+            AppCategory::DeveloperTool => "devel",
+            AppCategory::Education => "education",
+            AppCategory::Entertainment => "misc",
+            AppCategory::Finance => "misc",
+            AppCategory::Game => "games",
+            AppCategory::ActionGame => "games",
+            AppCategory::AdventureGame => "games",
+            AppCategory::ArcadeGame => "games",
+            AppCategory::BoardGame => "games",
+            AppCategory::CardGame => "games",
+            AppCategory::CasinoGame => "games",
+            AppCategory::DiceGame => "games",
+            AppCategory::EducationalGame => "games",
+            AppCategory::FamilyGame => "games",
+            AppCategory::KidsGame => "games",
+            AppCategory::MusicGame => "games",
+            AppCategory::PuzzleGame => "games",
+            AppCategory::RacingGame => "games",
+            AppCategory::RolePlayingGame => "games",
+            AppCategory::SimulationGame => "games",
+            AppCategory::SportsGame => "games",
+            AppCategory::StrategyGame => "games",
+            AppCategory::TriviaGame => "games",
+            AppCategory::WordGame => "games",
+            AppCategory::GraphicsAndDesign => "graphics",
+            AppCategory::HealthcareAndFitness => "science",
+            AppCategory::Lifestyle => "misc",
+            AppCategory::Medical => "science",
+            AppCategory::Music => "sound",
+            AppCategory::News => "news",
+            AppCategory::Photography => "graphics",
+            AppCategory::Productivity => "misc",
+            AppCategory::Reference => "doc",
+            AppCategory::SocialNetworking => "net",
+            AppCategory::Sports => "misc",
+            AppCategory::Travel => "misc",
+            AppCategory::Utility => "utils",
+            AppCategory::Video => "video",
+            AppCategory::Weather => "science",
+        }
+    }
+
     /// Map an AppCategory to the closest LSApplicationCategoryType value that
     /// matches that category.
     pub fn osx_application_category_type(&self) -> &'static str {
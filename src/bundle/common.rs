@@ -1,9 +1,13 @@
 use ::ResultExt;
+use flate2;
+use image;
 use std;
+use std::env;
 use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
 use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use term;
 use walkdir;
 
@@ -19,6 +23,12 @@ pub fn is_retina<P: AsRef<Path>>(path: P) -> bool {
         .unwrap_or(false)
 }
 
+/// Resizes an icon image to a square of the given size, for icon formats that
+/// need images of particular sizes.
+pub fn resize_icon(icon: &image::DynamicImage, size: u32) -> image::DynamicImage {
+    icon.resize_exact(size, size, image::Lanczos3)
+}
+
 /// Creates a new file at the given path, creating any parent directories as
 /// needed.
 pub fn create_file(path: &Path) -> ::Result<BufWriter<File>> {
@@ -129,6 +139,81 @@ pub fn resource_relpath(path: &Path) -> PathBuf {
     dest
 }
 
+/// Returns true if the given path is non-empty and consists only of normal
+/// components (i.e. no `..`, `.` or root components).
+pub fn is_plain_relative_path(path: &Path) -> bool {
+    path.components().next().is_some() &&
+        path.components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Escapes the characters that can't appear literally in XML text or
+/// attribute values.
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the timestamp given by the `SOURCE_DATE_EPOCH` environment
+/// variable, if it is set.  See
+/// https://reproducible-builds.org/specs/source-date-epoch/ for details.
+pub fn source_date_epoch() -> ::Result<Option<u64>> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => match value.trim().parse::<u64>() {
+            Ok(epoch) => Ok(Some(epoch)),
+            Err(_) => bail!("Invalid SOURCE_DATE_EPOCH value: {:?}", value),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// Creates a gzip encoder that writes to the given writer, compressing at the
+/// given level (0-9).  If a `source_date_epoch` is given, it is recorded as
+/// the modification time in the gzip header, in place of the current time.
+pub fn gzip_encoder<W: Write>(writer: W, level: u32, source_date_epoch: Option<u64>)
+                              -> flate2::write::GzEncoder<W> {
+    let mtime = source_date_epoch.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    });
+    flate2::GzBuilder::new()
+        .mtime(mtime as u32)
+        .write(writer, flate2::Compression::new(level))
+}
+
+/// Returns the modification time recorded in the given metadata, in seconds
+/// since the Unix epoch (or zero, if the platform doesn't provide one).
+pub fn modified_time(metadata: &fs::Metadata) -> u64 {
+    metadata.modified().ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns true if the given metadata belongs to a file that any user is
+/// allowed to execute.  Always returns false on platforms without Unix
+/// permissions.
+#[cfg(unix)]
+pub fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
 /// Prints a message to stderr, in the same format that `cargo` uses,
 /// indicating that we are creating a bundle with the given filename.
 pub fn print_bundling(filename: &str) -> ::Result<()> {
@@ -232,8 +317,9 @@ pub fn print_error(error: &::Error) -> ::Result<()> {
 mod tests {
     use std;
     use std::io::Write;
-    use std::path::PathBuf;
-    use super::{copy_dir, create_file, symlink_file, is_retina, resource_relpath};
+    use std::path::{Path, PathBuf};
+    use super::{copy_dir, create_file, is_plain_relative_path, is_retina, resource_relpath,
+                symlink_file, xml_escape};
     use tempfile;
 
     #[test]
@@ -294,4 +380,21 @@ mod tests {
         assert_eq!(resource_relpath(&PathBuf::from("/home/ferris/crab.png")),
                    PathBuf::from("_root_/home/ferris/crab.png"));
     }
+
+    #[test]
+    fn plain_relative_paths() {
+        assert!(is_plain_relative_path(Path::new("example/example.conf")));
+        assert!(!is_plain_relative_path(Path::new("")));
+        assert!(!is_plain_relative_path(Path::new("./example.conf")));
+        assert!(!is_plain_relative_path(Path::new("../passwd")));
+        assert!(!is_plain_relative_path(Path::new("example/../../passwd")));
+        assert!(!is_plain_relative_path(Path::new("/etc/passwd")));
+    }
+
+    #[test]
+    fn xml_escaping() {
+        assert_eq!(xml_escape("Plain text"), "Plain text");
+        assert_eq!(xml_escape("<a href=\"x\">Tom & Jerry's</a>"),
+                   "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+    }
 }
@@ -2,37 +2,57 @@
 //
 // foobar_1.2.3_i386.deb   # Actually an ar archive
 //     debian-binary           # Specifies deb format version (2.0 in our case)
-//     control.tar.gz          # Contains files controlling the installation:
+//     control.tar.gz          # Contains files controlling the installation
+//                             # (.tar.xz, .tar.zst or .tar, per deb_compression):
 //         control                  # Basic package metadata
-//         md5sums                  # Checksums for files in data.tar.gz below
+//         md5sums                  # Checksums for files in data.tar below
+//         conffiles                # List of configuration files (optional)
+//         preinst                  # Pre-installation script (optional)
 //         postinst                 # Post-installation script (optional)
 //         prerm                    # Pre-uninstallation script (optional)
-//     data.tar.gz             # Contains files to be installed:
+//         postrm                   # Post-uninstallation script (optional)
+//     data.tar.gz             # Contains files to be installed (compressed
+//                             # the same way as control.tar):
 //         usr/bin/foobar                            # Binary executable file
 //         usr/share/applications/foobar.desktop     # Desktop file (for apps)
 //         usr/share/icons/hicolor/...               # Icon files (for apps)
 //         usr/lib/foobar/...                        # Other resource files
+//         etc/...                                   # Configuration files
 //
 // For cargo-bundle, we put bundle resource files under /usr/lib/package_name/,
 // and then generate the desktop file and control file from the bundle
-// metadata, as well as generating the md5sums file.  Currently we do not
-// generate postinst or prerm files.
+// metadata, as well as generating the md5sums file.  The two tar archives are
+// compressed according to the `deb_compression` setting: gzip by default
+// (.tar.gz), xz (.tar.xz), zstd (.tar.zst), or none at all (.tar).  The
+// maintainer scripts (preinst, postinst, prerm and postrm) are copied from
+// the paths given in the bundle settings, if any, as are configuration files,
+// which are installed under /etc/ and listed in the conffiles file so that
+// dpkg preserves local changes to them across upgrades.
 
 use super::common;
-use {ResultExt, Settings};
+use super::elf;
+use super::settings::{DebCompression, FileAssociation};
+use {PackageType, ResultExt, Settings};
 use ar;
+use flate2;
 use icns;
 use image::{self, GenericImage, ImageDecoder};
 use image::png::{PNGDecoder, PNGEncoder};
-use libflate::gzip;
 use md5;
-use std::collections::BTreeSet;
+use std::cmp::min;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
 use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use tar;
 use walkdir::WalkDir;
+use xz2;
+use zstd;
+
+// The names of the maintainer scripts that may appear in the control archive.
+const MAINTAINER_SCRIPTS: &[&str] = &["preinst", "postinst", "prerm", "postrm"];
 
 pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
     let arch = match settings.binary_arch() {
@@ -43,7 +63,7 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
         other => other,
     };
     let package_base_name = format!("{}_{}_{}", settings.binary_name(),
-                                    settings.version_string(), arch);
+                                    settings.version()?.linux_version(), arch);
     let package_name = format!("{}.deb", package_base_name);
     common::print_bundling(&package_name)?;
     let base_dir = settings.project_out_directory().join("bundle/deb");
@@ -56,29 +76,22 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
     let package_path = base_dir.join(package_name);
 
     // Generate data files.
-    let data_dir = package_dir.join("data");
-    let binary_dest = data_dir.join("usr/bin").join(settings.binary_name());
-    common::copy_file(settings.binary_path(), &binary_dest).chain_err(|| {
-        "Failed to copy binary file"
-    })?;
-    transfer_resource_files(settings, &data_dir).chain_err(|| {
-        "Failed to copy resource files"
-    })?;
-    generate_icon_files(settings, &data_dir).chain_err(|| {
-        "Failed to create icon files"
-    })?;
-    generate_desktop_file(settings, &data_dir).chain_err(|| {
-        "Failed to create desktop file"
+    let data_dir = generate_data(settings, PackageType::Deb, &package_dir)?;
+    let control_dir = package_dir.join("control");
+    transfer_conffiles(settings, &data_dir, &control_dir).chain_err(|| {
+        "Failed to copy configuration files"
     })?;
 
     // Generate control files.
-    let control_dir = package_dir.join("control");
     generate_control_file(settings, arch, &control_dir, &data_dir).chain_err(|| {
         "Failed to create control file"
     })?;
     generate_md5sums(&control_dir, &data_dir).chain_err(|| {
         "Failed to create md5sums file"
     })?;
+    copy_maintainer_scripts(settings, &control_dir).chain_err(|| {
+        "Failed to copy maintainer scripts"
+    })?;
 
     // Generate `debian-binary` file; see
     // http://www.tldp.org/HOWTO/Debian-Binary-Package-Building-HOWTO/x60.html#AEN66
@@ -87,27 +100,61 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
         "Failed to create debian-binary file"
     })?;
 
-    // Apply tar/gzip/ar to create the final package file.
-    let control_tar_gz_path = tar_and_gzip_dir(control_dir).chain_err(|| {
-        "Failed to tar/gzip control directory"
+    // Apply tar/compression/ar to create the final package file.
+    let control_tar_path = tar_and_compress_dir(control_dir, settings).chain_err(|| {
+        "Failed to tar/compress control directory"
     })?;
-    let data_tar_gz_path = tar_and_gzip_dir(data_dir).chain_err(|| {
-        "Failed to tar/gzip data directory"
+    let data_tar_path = tar_and_compress_dir(data_dir, settings).chain_err(|| {
+        "Failed to tar/compress data directory"
     })?;
-    create_archive(vec![debian_binary_path, control_tar_gz_path, data_tar_gz_path],
-                   &package_path).chain_err(|| {
+    let source_date_epoch = settings.source_date_epoch();
+    create_archive(vec![debian_binary_path, control_tar_path, data_tar_path],
+                   &package_path, source_date_epoch).chain_err(|| {
         "Failed to create package archive"
     })?;
     Ok(vec![package_path])
 }
 
+/// Generate the files to be installed (the binary, resources, icons and
+/// desktop file) under a `data` directory within the `package_dir`, and
+/// return the path to that directory.  The resulting layout is shared with
+/// the RPM bundler.
+pub fn generate_data(settings: &Settings, package_type: PackageType, package_dir: &Path)
+                     -> ::Result<PathBuf> {
+    let data_dir = package_dir.join("data");
+    let binary_dest = data_dir.join("usr/bin").join(settings.binary_name());
+    common::copy_file(settings.binary_path(), &binary_dest).chain_err(|| {
+        "Failed to copy binary file"
+    })?;
+    transfer_resource_files(settings, package_type, &data_dir).chain_err(|| {
+        "Failed to copy resource files"
+    })?;
+    generate_icon_files(settings, &data_dir).chain_err(|| {
+        "Failed to create icon files"
+    })?;
+    generate_desktop_file(settings, &data_dir).chain_err(|| {
+        "Failed to create desktop file"
+    })?;
+    generate_mime_package_file(settings, &data_dir).chain_err(|| {
+        "Failed to create MIME package file"
+    })?;
+    Ok(data_dir)
+}
+
 /// Generate the application desktop file and store it under the `data_dir`.
 fn generate_desktop_file(settings: &Settings, data_dir: &Path) -> ::Result<()> {
     let bin_name = settings.binary_name();
     let desktop_file_name = format!("{}.desktop", bin_name);
     let desktop_file_path = data_dir.join("usr/share/applications").join(desktop_file_name);
     let file = &mut common::create_file(&desktop_file_path)?;
-    let mime_types = settings.linux_mime_types().iter().fold(
+    let mut mime_types: Vec<String> = settings.linux_mime_types().to_vec();
+    for association in settings.file_associations() {
+        mime_types.extend(association.mime_type.clone());
+    }
+    for scheme in settings.url_schemes() {
+        mime_types.push(format!("x-scheme-handler/{}", scheme));
+    }
+    let mime_types = mime_types.iter().fold(
         "".to_owned(),
         |acc, s| format!("{}{};", acc, s)
     );
@@ -124,6 +171,10 @@ fn generate_desktop_file(settings: &Settings, data_dir: &Path) -> ::Result<()> {
     let exec;
     match settings.linux_exec_args() {
         Some(args) => exec = format!("{} {}", bin_name, args),
+        // Apps that open files or URLs need to be passed them.
+        None if !settings.file_associations().is_empty() || !settings.url_schemes().is_empty() => {
+            exec = format!("{} %U", bin_name)
+        }
         None => exec = bin_name.to_owned(),
     }
     write!(file, "Exec={}\n", exec)?;
@@ -137,25 +188,81 @@ fn generate_desktop_file(settings: &Settings, data_dir: &Path) -> ::Result<()> {
     Ok(())
 }
 
+/// Generate a shared-mime-info package file under the `data_dir`, which maps
+/// the file extensions from the `file_associations` setting to their MIME
+/// types, if there are any.
+fn generate_mime_package_file(settings: &Settings, data_dir: &Path) -> ::Result<()> {
+    let associations: Vec<_> = settings.file_associations().iter()
+        .filter(|association| association.mime_type.is_some())
+        .collect();
+    if associations.is_empty() {
+        return Ok(());
+    }
+    let file_name = format!("{}.xml", settings.binary_name());
+    let file_path = data_dir.join("usr/share/mime/packages").join(file_name);
+    let mut file = common::create_file(&file_path)?;
+    write_mime_package(&mut file, &associations)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes a shared MIME-info package for the given file associations, which
+/// must all have MIME types.
+fn write_mime_package<W: Write>(writer: &mut W, associations: &[&FileAssociation])
+                                -> ::Result<()> {
+    // For more information about the format of this file, see
+    // https://specifications.freedesktop.org/shared-mime-info-spec/latest/
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer,
+             "<mime-info xmlns=\"http://www.freedesktop.org/standards/shared-mime-info\">")?;
+    for association in associations {
+        writeln!(writer, "  <mime-type type=\"{}\">",
+                 common::xml_escape(association.mime_type.as_ref().unwrap()))?;
+        writeln!(writer, "    <comment>{}</comment>",
+                 common::xml_escape(&association.display_name()))?;
+        for extension in association.extensions.iter() {
+            writeln!(writer, "    <glob pattern=\"*.{}\"/>", common::xml_escape(extension))?;
+        }
+        writeln!(writer, "  </mime-type>")?;
+    }
+    writeln!(writer, "</mime-info>")?;
+    Ok(())
+}
+
 fn generate_control_file(settings: &Settings, arch: &str, control_dir: &Path, data_dir: &Path) -> ::Result<()> {
     // For more information about the format of this file, see
     // https://www.debian.org/doc/debian-policy/ch-controlfields.html
     let dest_path = control_dir.join("control");
     let mut file = common::create_file(&dest_path)?;
-    writeln!(&mut file, "Package: {}",
-             str::replace(settings.bundle_name(), " ", "-")
-             .to_ascii_lowercase())?;
-    writeln!(&mut file, "Version: {}", settings.version_string())?;
+    writeln!(&mut file, "Package: {}", package_name(settings.bundle_name())?)?;
+    writeln!(&mut file, "Version: {}", settings.version()?.linux_version())?;
+    if let Some(section) = settings.debian_section() {
+        writeln!(&mut file, "Section: {}", section)?;
+    }
+    writeln!(&mut file, "Priority: {}", settings.debian_priority())?;
     writeln!(&mut file, "Architecture: {}", arch)?;
-    writeln!(&mut file, "Installed-Size: {}", total_dir_size(data_dir)?)?;
-    let authors = settings.authors_comma_separated().unwrap_or(String::new());
-    writeln!(&mut file, "Maintainer: {}", authors)?;
+    if settings.debian_essential() {
+        writeln!(&mut file, "Essential: yes")?;
+    }
+    writeln!(&mut file, "Installed-Size: {}", installed_size(data_dir)?)?;
+    writeln!(&mut file, "Maintainer: {}", maintainer(settings)?)?;
     if !settings.homepage_url().is_empty() {
         writeln!(&mut file, "Homepage: {}", settings.homepage_url())?;
     }
-    let dependencies = settings.debian_dependencies();
-    if !dependencies.is_empty() {
-        writeln!(&mut file, "Depends: {}", dependencies.join(", "))?;
+    let relationships = [
+        ("Pre-Depends", settings.debian_pre_dependencies().to_vec()),
+        ("Depends", collect_dependencies(settings, arch)?),
+        ("Recommends", settings.debian_recommends().to_vec()),
+        ("Suggests", settings.debian_suggests().to_vec()),
+        ("Breaks", settings.debian_breaks().to_vec()),
+        ("Conflicts", settings.debian_conflicts().to_vec()),
+        ("Provides", settings.debian_provides().to_vec()),
+        ("Replaces", settings.debian_replaces().to_vec()),
+    ];
+    for &(field, ref packages) in relationships.iter() {
+        if !packages.is_empty() {
+            writeln!(&mut file, "{}: {}", field, packages.join(", "))?;
+        }
     }
     let mut short_description = settings.short_description().trim();
     if short_description.is_empty() {
@@ -178,12 +285,116 @@ fn generate_control_file(settings: &Settings, arch: &str, control_dir: &Path, da
     Ok(())
 }
 
+/// Returns the entries for the `Depends:` field of the control file: the
+/// `deb_depends` from the bundle settings, followed (if `deb_auto_depends` is
+/// enabled) by the packages providing the shared libraries that the binary
+/// links against.  Each library's package is looked up in the
+/// `deb_soname_packages` table first, and then in the local dpkg database.
+fn collect_dependencies(settings: &Settings, arch: &str) -> ::Result<Vec<String>> {
+    let mut dependencies = settings.debian_dependencies().to_vec();
+    if !settings.debian_auto_dependencies() {
+        return Ok(dependencies);
+    }
+    let sonames = elf::needed_libraries(settings.binary_path()).chain_err(|| {
+        "Failed to detect shared library dependencies"
+    })?;
+    let mut dpkg_libraries = None;
+    let mut packages = BTreeSet::new();
+    for soname in sonames {
+        let package = match settings.debian_soname_package(&soname) {
+            Some(package) => Some(package.to_string()),
+            None => {
+                if dpkg_libraries.is_none() {
+                    dpkg_libraries = Some(read_dpkg_libraries(&dpkg_info_dir(), arch)?);
+                }
+                dpkg_libraries.as_ref().unwrap().get(&soname).cloned()
+            }
+        };
+        match package {
+            Some(package) => {
+                packages.insert(package);
+            }
+            None => {
+                common::print_warning(&format!("Could not find the Debian package providing \
+                                                {}; add it to deb_soname_packages or \
+                                                deb_depends",
+                                               soname))?;
+            }
+        }
+    }
+    for package in packages {
+        if !dependencies.iter().any(|dependency| dependency_package_name(dependency) == package) {
+            dependencies.push(package);
+        }
+    }
+    Ok(dependencies)
+}
+
+/// Returns the name of the (first) package named in a `Depends:` entry, e.g.
+/// `libssl1.1` for `libssl1.1 (>= 1.1.0)`.
+fn dependency_package_name(dependency: &str) -> &str {
+    dependency.split(|c: char| c.is_whitespace() || c == '(' || c == ':' || c == '|')
+        .next()
+        .unwrap_or("")
+}
+
+/// Returns the directory in which dpkg keeps the file lists of installed
+/// packages, honoring `DPKG_ADMINDIR` like dpkg itself does.
+fn dpkg_info_dir() -> PathBuf {
+    let admin_dir = env::var_os("DPKG_ADMINDIR").map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/var/lib/dpkg"));
+    admin_dir.join("info")
+}
+
+/// Reads the `*.list` files in the given dpkg info directory, and returns a
+/// map from the filename of every shared library installed by a package to
+/// the name of that package.  Packages built for an architecture other than
+/// `arch` are skipped.  If the directory doesn't exist (e.g. when not running
+/// on a Debian-based system), returns an empty map.
+fn read_dpkg_libraries(info_dir: &Path, arch: &str) -> ::Result<HashMap<String, String>> {
+    let mut libraries = HashMap::new();
+    if !info_dir.is_dir() {
+        return Ok(libraries);
+    }
+    let mut list_paths = Vec::new();
+    for entry in fs::read_dir(info_dir)? {
+        let path = entry?.path();
+        if path.extension() == Some(OsStr::new("list")) {
+            list_paths.push(path);
+        }
+    }
+    list_paths.sort();
+    for path in list_paths {
+        let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let mut parts = stem.splitn(2, ':');
+        let package = parts.next().unwrap().to_string();
+        if let Some(package_arch) = parts.next() {
+            if package_arch != arch && package_arch != "all" {
+                continue;
+            }
+        }
+        let contents = fs::read_to_string(&path).chain_err(|| {
+            format!("Failed to read {:?}", path)
+        })?;
+        for line in contents.lines() {
+            let filename = match Path::new(line).file_name() {
+                Some(filename) => filename.to_string_lossy(),
+                None => continue,
+            };
+            if filename.contains(".so") {
+                libraries.entry(filename.into_owned()).or_insert_with(|| package.clone());
+            }
+        }
+    }
+    Ok(libraries)
+}
+
 /// Create an `md5sums` file in the `control_dir` containing the MD5 checksums
 /// for each file within the `data_dir`.
 fn generate_md5sums(control_dir: &Path, data_dir: &Path) -> ::Result<()> {
     let md5sums_path = control_dir.join("md5sums");
     let mut md5sums_file = common::create_file(&md5sums_path)?;
-    for entry in WalkDir::new(data_dir) {
+    for entry in WalkDir::new(data_dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
         let entry = entry?;
         let path = entry.path();
         if path.is_dir() {
@@ -206,13 +417,67 @@ fn generate_md5sums(control_dir: &Path, data_dir: &Path) -> ::Result<()> {
     Ok(())
 }
 
+/// Copy the maintainer scripts specified in the bundle settings (if any) into
+/// the `control_dir`.  They are marked executable when the control directory
+/// is archived (see `file_mode`).
+fn copy_maintainer_scripts(settings: &Settings, control_dir: &Path) -> ::Result<()> {
+    let scripts = [
+        ("preinst", settings.debian_preinst_script()),
+        ("postinst", settings.debian_postinst_script()),
+        ("prerm", settings.debian_prerm_script()),
+        ("postrm", settings.debian_postrm_script()),
+    ];
+    for &(name, script) in scripts.iter() {
+        if let Some(src) = script {
+            let dest = control_dir.join(name);
+            common::copy_file(src, &dest).chain_err(|| {
+                format!("Failed to copy {} script {:?}", name, src)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the configuration files specified in the bundle settings (if any) to
+/// their install paths under the `data_dir`, and list those paths in a
+/// `conffiles` file in the `control_dir`.
+fn transfer_conffiles(settings: &Settings, data_dir: &Path, control_dir: &Path)
+                      -> ::Result<()> {
+    let conffiles = match settings.debian_conffiles() {
+        Some(conffiles) if !conffiles.is_empty() => conffiles,
+        _ => return Ok(()),
+    };
+    let mut conffiles_file = common::create_file(&control_dir.join("conffiles"))?;
+    for (install_path, src) in conffiles {
+        let rel_path = conffile_relative_path(install_path)?;
+        let dest = data_dir.join("etc").join(rel_path);
+        common::copy_file(src, &dest).chain_err(|| {
+            format!("Failed to copy configuration file {:?}", src)
+        })?;
+        // List the normalized path, which is where the file actually ends
+        // up, rather than the path as written in the settings.
+        writeln!(conffiles_file, "{}", Path::new("/etc").join(rel_path).display())?;
+    }
+    conffiles_file.flush()?;
+    Ok(())
+}
+
+/// Returns the path of a configuration file relative to /etc, given its
+/// install path, which must be a file under /etc.
+fn conffile_relative_path(install_path: &str) -> ::Result<&Path> {
+    match Path::new(install_path).strip_prefix("/etc") {
+        Ok(rel_path) if common::is_plain_relative_path(rel_path) => Ok(rel_path),
+        _ => bail!("Configuration file path {:?} must be a file under /etc/", install_path),
+    }
+}
+
 /// Copy the bundle's resource files into an appropriate directory under the
 /// `data_dir`.
-fn transfer_resource_files(settings: &Settings, data_dir: &Path) -> ::Result<()> {
+fn transfer_resource_files(settings: &Settings, package_type: PackageType, data_dir: &Path)
+                           -> ::Result<()> {
     let resource_dir = data_dir.join("usr/lib").join(settings.binary_name());
-    for src in settings.resource_files() {
-        let src = src?;
-        let dest = resource_dir.join(common::resource_relpath(&src));
+    for (src, dest) in settings.resource_files(package_type)? {
+        let dest = resource_dir.join(dest);
         common::copy_file(&src, &dest).chain_err(|| {
             format!("Failed to copy resource file {:?}", src)
         })?;
@@ -288,62 +553,313 @@ fn create_file_with_data<P: AsRef<Path>>(path: P, data: &str) -> ::Result<()> {
     Ok(())
 }
 
-/// Computes the total size, in bytes, of the given directory and all of its
-/// contents.
-fn total_dir_size(dir: &Path) -> ::Result<u64> {
+/// Computes the disk space, in KiB, needed to install the contents of the
+/// given directory.  Like `dpkg-gencontrol`, each regular file counts as its
+/// size rounded up to a whole KiB, and every other entry counts as 1 KiB.
+fn installed_size(dir: &Path) -> ::Result<u64> {
     let mut total: u64 = 0;
-    for entry in WalkDir::new(&dir) {
-        total += entry?
-            .metadata()?
-            .len();
+    for entry in WalkDir::new(dir).min_depth(1) {
+        let metadata = entry?.metadata()?;
+        total += if metadata.is_file() { metadata.len().div_ceil(1024) } else { 1 };
     }
     Ok(total)
 }
 
+/// Returns the name of the deb package, derived from the given bundle name by
+/// lowercasing it and replacing spaces and underscores with hyphens.  Fails
+/// if the result still isn't a valid package name, which must consist only of
+/// lowercase letters, digits, `+`, `-` and `.`, be at least two characters
+/// long, and start with a letter or digit.
+fn package_name(bundle_name: &str) -> ::Result<String> {
+    let name = bundle_name.to_ascii_lowercase().replace([' ', '_'], "-");
+    let is_valid = name.len() >= 2 &&
+        name.starts_with(|c: char| c.is_ascii_alphanumeric()) &&
+        name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() ||
+                             c == '+' || c == '-' || c == '.');
+    if !is_valid {
+        bail!("{:?} is not a valid Debian package name; package names may only contain \
+               lowercase letters, digits, '+', '-' and '.', must be at least two \
+               characters long, and must start with a letter or digit (set `name` in \
+               [package.metadata.bundle] to change it)",
+              name);
+    }
+    Ok(name)
+}
+
+/// Returns the value of the `Maintainer:` field, which must name a single
+/// person or team in the form `Name <email>`.  This comes from the
+/// `deb_maintainer` setting if present, or else from the first author that
+/// includes an email address.
+fn maintainer(settings: &Settings) -> ::Result<String> {
+    if let Some(maintainer) = settings.debian_maintainer() {
+        if !is_valid_maintainer(maintainer) {
+            bail!("Invalid deb_maintainer {:?}; it must be of the form \"Name <email>\"",
+                  maintainer);
+        }
+        return Ok(maintainer.to_string());
+    }
+    match settings.author_names().iter().find(|author| is_valid_maintainer(author)) {
+        Some(author) => Ok(author.trim().to_string()),
+        None => bail!("No author has an email address to use as the deb package's \
+                       maintainer; set deb_maintainer to \"Name <email>\""),
+    }
+}
+
+/// Returns true if the given string is of the form `Name <email>`.
+fn is_valid_maintainer(maintainer: &str) -> bool {
+    let maintainer = maintainer.trim();
+    if !maintainer.ends_with('>') {
+        return false;
+    }
+    match maintainer.find('<') {
+        Some(index) => {
+            let name = maintainer[..index].trim();
+            let email = &maintainer[index + 1..maintainer.len() - 1];
+            !name.is_empty() && !name.contains(',') && email.contains('@') &&
+                !email.contains(|c: char| c == '<' || c == '>' || c.is_whitespace())
+        }
+        None => false,
+    }
+}
+
+/// Returns the permission bits that the file at `rel_path` (relative to the
+/// control or data directory) should have in the package.  Maintainer
+/// scripts, binaries, and files that are already executable get 0755; all
+/// other files get 0644.
+fn file_mode(rel_path: &Path, metadata: &fs::Metadata) -> u32 {
+    let is_script = MAINTAINER_SCRIPTS.iter().any(|name| rel_path == Path::new(name));
+    if is_script || rel_path.starts_with("usr/bin") || common::is_executable(metadata) {
+        0o755
+    } else {
+        0o644
+    }
+}
+
 /// Writes a tar file to the given writer containing the given directory.
-fn create_tar_from_dir<P: AsRef<Path>, W: Write>(src_dir: P, dest_file: W) -> ::Result<W> {
+/// Every entry is owned by root:root and gets normalized permissions.
+/// Entries are sorted by name, and modification times are clamped to the
+/// `source_date_epoch` (if any).
+fn create_tar_from_dir<P: AsRef<Path>, W: Write>(src_dir: P, dest_file: W,
+                                                 source_date_epoch: Option<u64>)
+                                                 -> ::Result<W> {
     let src_dir = src_dir.as_ref();
     let mut tar_builder = tar::Builder::new(dest_file);
-    for entry in WalkDir::new(&src_dir) {
+    for entry in WalkDir::new(&src_dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
         let entry = entry?;
         let src_path = entry.path();
         if src_path == src_dir {
             continue;
         }
         let dest_path = src_path.strip_prefix(&src_dir).unwrap();
+        let metadata = entry.metadata()?;
+        let mut mtime = common::modified_time(&metadata);
+        if let Some(epoch) = source_date_epoch {
+            mtime = min(mtime, epoch);
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+        header.set_mtime(mtime);
         if entry.file_type().is_dir() {
-            tar_builder.append_dir(dest_path, src_path)?;
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            tar_builder.append_data(&mut header, dest_path, io::empty())?;
         } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(file_mode(dest_path, &metadata));
+            header.set_size(metadata.len());
             let mut src_file = fs::File::open(src_path)?;
-            tar_builder.append_file(dest_path, &mut src_file)?;
+            tar_builder.append_data(&mut header, dest_path, &mut src_file)?;
         }
     }
     let dest_file = tar_builder.into_inner()?;
     Ok(dest_file)
 }
 
-/// Creates a `.tar.gz` file from the given directory (placing the new file
-/// within the given directory's parent directory), then deletes the original
-/// directory and returns the path to the new file.
-fn tar_and_gzip_dir<P: AsRef<Path>>(src_dir: P) -> ::Result<PathBuf> {
+/// Creates a (possibly compressed) tar file from the given directory, using
+/// the deb compression settings, and placing the new file within the given
+/// directory's parent directory.  Returns the path to the new file.
+fn tar_and_compress_dir<P: AsRef<Path>>(src_dir: P, settings: &Settings) -> ::Result<PathBuf> {
     let src_dir = src_dir.as_ref();
-    let dest_path = src_dir.with_extension("tar.gz");
+    let compression = settings.debian_compression();
+    let dest_path = src_dir.with_extension(compression.tar_extension());
     let dest_file = common::create_file(&dest_path)?;
-    let gzip_encoder = gzip::Encoder::new(dest_file)?;
-    let gzip_encoder = create_tar_from_dir(src_dir, gzip_encoder)?;
-    let mut dest_file = gzip_encoder.finish().into_result()?;
+    let encoder = CompressedWriter::new(dest_file, compression,
+                                        settings.debian_compression_level(),
+                                        settings.source_date_epoch())?;
+    let encoder = create_tar_from_dir(src_dir, encoder, settings.source_date_epoch())?;
+    let mut dest_file = encoder.finish()?;
     dest_file.flush()?;
     Ok(dest_path)
 }
 
+/// A writer that compresses everything written to it with one of the codecs
+/// supported for deb package members.  This is also used for the tar
+/// archives of the `archive` format.
+pub enum CompressedWriter<W: Write> {
+    Gzip(flate2::write::GzEncoder<W>),
+    Xz(xz2::write::XzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    None(W),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(writer: W, compression: DebCompression, level: u32,
+               source_date_epoch: Option<u64>) -> ::Result<CompressedWriter<W>> {
+        Ok(match compression {
+            DebCompression::Gzip => {
+                CompressedWriter::Gzip(common::gzip_encoder(writer, level, source_date_epoch))
+            }
+            DebCompression::Xz => CompressedWriter::Xz(xz2::write::XzEncoder::new(writer, level)),
+            DebCompression::Zstd => {
+                CompressedWriter::Zstd(zstd::Encoder::new(writer, level as i32)?)
+            }
+            DebCompression::None => CompressedWriter::None(writer),
+        })
+    }
+
+    /// Finishes the compressed stream and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            CompressedWriter::Gzip(encoder) => encoder.finish(),
+            CompressedWriter::Xz(encoder) => encoder.finish(),
+            CompressedWriter::Zstd(encoder) => encoder.finish(),
+            CompressedWriter::None(writer) => Ok(writer),
+        }
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            CompressedWriter::Gzip(ref mut encoder) => encoder.write(buf),
+            CompressedWriter::Xz(ref mut encoder) => encoder.write(buf),
+            CompressedWriter::Zstd(ref mut encoder) => encoder.write(buf),
+            CompressedWriter::None(ref mut writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            CompressedWriter::Gzip(ref mut encoder) => encoder.flush(),
+            CompressedWriter::Xz(ref mut encoder) => encoder.flush(),
+            CompressedWriter::Zstd(ref mut encoder) => encoder.flush(),
+            CompressedWriter::None(ref mut writer) => writer.flush(),
+        }
+    }
+}
+
 /// Creates an `ar` archive from the given source files and writes it to the
-/// given destination path.
-fn create_archive(srcs: Vec<PathBuf>, dest: &Path) -> ::Result<()> {
+/// given destination path.  Like `dpkg-deb`, every member is owned by root
+/// with mode 0644, and modification times are clamped to the
+/// `source_date_epoch` (if any).
+fn create_archive(srcs: Vec<PathBuf>, dest: &Path, source_date_epoch: Option<u64>)
+                  -> ::Result<()> {
     let mut builder = ar::Builder::new(common::create_file(&dest)?);
     for path in &srcs {
-        builder.append_path(path)?;
+        let metadata = path.metadata()?;
+        let mut mtime = common::modified_time(&metadata);
+        if let Some(epoch) = source_date_epoch {
+            mtime = min(mtime, epoch);
+        }
+        let identifier = path.file_name().unwrap().to_string_lossy().into_owned();
+        let mut header = ar::Header::new(identifier.into_bytes(), metadata.len());
+        header.set_mtime(mtime);
+        header.set_mode(0o100644);
+        builder.append(&header, File::open(path)?)?;
     }
     builder.into_inner()?
         .flush()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{conffile_relative_path, dependency_package_name, is_valid_maintainer,
+                package_name, read_dpkg_libraries, write_mime_package};
+    use bundle::settings::FileAssociation;
+    use std::fs;
+    use std::path::Path;
+    use tempfile;
+
+    #[test]
+    fn conffile_paths() {
+        assert_eq!(conffile_relative_path("/etc/example.conf").unwrap(),
+                   Path::new("example.conf"));
+        assert_eq!(conffile_relative_path("/etc//example/./app.conf").unwrap(),
+                   Path::new("example/app.conf"));
+        assert!(conffile_relative_path("/etc").is_err());
+        assert!(conffile_relative_path("/etc/../passwd").is_err());
+        assert!(conffile_relative_path("/usr/share/example.conf").is_err());
+        assert!(conffile_relative_path("etc/example.conf").is_err());
+    }
+
+    #[test]
+    fn dependency_names() {
+        assert_eq!(dependency_package_name("libssl1.1"), "libssl1.1");
+        assert_eq!(dependency_package_name("libssl1.1 (>= 1.1.0)"), "libssl1.1");
+        assert_eq!(dependency_package_name("libgl1:amd64"), "libgl1");
+        assert_eq!(dependency_package_name("libgtk-3-0|libgtk2.0-0"), "libgtk-3-0");
+    }
+
+    #[test]
+    fn dpkg_library_lookup() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("libfoo1:amd64.list"),
+                  "/usr/lib/x86_64-linux-gnu\n\
+                   /usr/lib/x86_64-linux-gnu/libfoo.so.1\n").unwrap();
+        fs::write(tmp.path().join("libfoo1:i386.list"),
+                  "/usr/lib/i386-linux-gnu/libfoo.so.1\n\
+                   /usr/lib/i386-linux-gnu/libfoo-i386-only.so.1\n").unwrap();
+        fs::write(tmp.path().join("libbar2.list"),
+                  "/usr/lib/libbar.so.2\n/usr/share/doc/libbar2/README\n").unwrap();
+        let libraries = read_dpkg_libraries(tmp.path(), "amd64").unwrap();
+        assert_eq!(libraries.get("libfoo.so.1").map(String::as_str), Some("libfoo1"));
+        assert_eq!(libraries.get("libbar.so.2").map(String::as_str), Some("libbar2"));
+        assert_eq!(libraries.get("libfoo-i386-only.so.1"), None);
+        assert_eq!(libraries.get("README"), None);
+        assert!(read_dpkg_libraries(&tmp.path().join("missing"), "amd64").unwrap().is_empty());
+    }
+
+    #[test]
+    fn package_names() {
+        assert_eq!(package_name("Example App").unwrap(), "example-app");
+        assert_eq!(package_name("my_app2").unwrap(), "my-app2");
+        assert_eq!(package_name("gtk+.viewer").unwrap(), "gtk+.viewer");
+        assert!(package_name("x").is_err());
+        assert!(package_name("-app").is_err());
+        assert!(package_name("Café").is_err());
+        assert!(package_name("app!").is_err());
+    }
+
+    #[test]
+    fn maintainer_validation() {
+        assert!(is_valid_maintainer("Jane Doe <jane@example.com>"));
+        assert!(is_valid_maintainer(" Example Team <team@lists.example.com> "));
+        assert!(!is_valid_maintainer("Jane Doe"));
+        assert!(!is_valid_maintainer("<jane@example.com>"));
+        assert!(!is_valid_maintainer("Jane Doe <jane>"));
+        assert!(!is_valid_maintainer("Jane Doe, John Roe <john@example.com>"));
+    }
+
+    #[test]
+    fn mime_package() {
+        let association = FileAssociation {
+            extensions: vec!["foo".to_string(), "foo2".to_string()],
+            mime_type: Some("application/x-foo".to_string()),
+            description: Some("Foo & Bar <document>".to_string()),
+        };
+        let mut output = Vec::new();
+        write_mime_package(&mut output, &[&association]).unwrap();
+        let xml = String::from_utf8(output).unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        assert!(xml.contains("\n  <mime-type type=\"application/x-foo\">\n"));
+        assert!(xml.contains("\n    <comment>Foo &amp; Bar &lt;document&gt;</comment>\n"));
+        assert!(xml.contains("\n    <glob pattern=\"*.foo\"/>\n"));
+        assert!(xml.contains("\n    <glob pattern=\"*.foo2\"/>\n"));
+        assert!(xml.ends_with("\n  </mime-type>\n</mime-info>\n"));
+    }
+}
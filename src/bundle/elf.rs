@@ -0,0 +1,202 @@
+// A minimal ELF reader, just capable enough to find the shared libraries that
+// an executable links against (its DT_NEEDED entries).
+//
+// The dynamic section is located via the program headers (rather than the
+// section headers, which may have been stripped), and the string table
+// address that it records is translated into a file offset using the PT_LOAD
+// segments.  Both 32- and 64-bit files of either byte order are supported.
+//
+// For more information about the format, see
+// https://refspecs.linuxfoundation.org/elf/gabi4+/contents.html
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use ResultExt;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+
+/// Returns the sonames of the shared libraries that the ELF file at the given
+/// path depends on, in the order in which they are listed.
+pub fn needed_libraries(path: &Path) -> ::Result<Vec<String>> {
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .chain_err(|| format!("Failed to read {:?}", path))?;
+    let elf = ElfFile::parse(&data).chain_err(|| format!("Failed to parse ELF file {:?}", path))?;
+    elf.needed_libraries()
+}
+
+struct Segment {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+struct ElfFile<'a> {
+    data: &'a [u8],
+    is_64_bit: bool,
+    is_big_endian: bool,
+    segments: Vec<Segment>,
+}
+
+impl<'a> ElfFile<'a> {
+    fn parse(data: &'a [u8]) -> ::Result<ElfFile<'a>> {
+        if data.len() < 16 || &data[0..4] != ELF_MAGIC {
+            bail!("Not an ELF file");
+        }
+        let is_64_bit = match data[4] {
+            ELFCLASS32 => false,
+            ELFCLASS64 => true,
+            class => bail!("Unsupported ELF class {}", class),
+        };
+        let is_big_endian = match data[5] {
+            ELFDATA2LSB => false,
+            ELFDATA2MSB => true,
+            encoding => bail!("Unsupported ELF data encoding {}", encoding),
+        };
+        let mut elf = ElfFile { data, is_64_bit, is_big_endian, segments: Vec::new() };
+        let (phoff, phentsize, phnum) = if is_64_bit {
+            (elf.read_u64(0x20)?, elf.read_u16(0x36)?, elf.read_u16(0x38)?)
+        } else {
+            (elf.read_u32(0x1c)? as u64, elf.read_u16(0x2a)?, elf.read_u16(0x2c)?)
+        };
+        for index in 0..phnum as u64 {
+            let start = phoff + index * phentsize as u64;
+            let segment = if is_64_bit {
+                Segment {
+                    kind: elf.read_u32(start)?,
+                    offset: elf.read_u64(start + 8)?,
+                    vaddr: elf.read_u64(start + 16)?,
+                    filesz: elf.read_u64(start + 32)?,
+                }
+            } else {
+                Segment {
+                    kind: elf.read_u32(start)?,
+                    offset: elf.read_u32(start + 4)? as u64,
+                    vaddr: elf.read_u32(start + 8)? as u64,
+                    filesz: elf.read_u32(start + 16)? as u64,
+                }
+            };
+            elf.segments.push(segment);
+        }
+        Ok(elf)
+    }
+
+    fn needed_libraries(&self) -> ::Result<Vec<String>> {
+        let dynamic = match self.segments.iter().find(|segment| segment.kind == PT_DYNAMIC) {
+            Some(segment) => segment,
+            None => return Ok(Vec::new()), // Statically linked
+        };
+        let entry_size = if self.is_64_bit { 16 } else { 8 };
+        let mut needed_offsets = Vec::new();
+        let mut strtab_address = None;
+        let mut offset = dynamic.offset;
+        while offset + entry_size <= dynamic.offset + dynamic.filesz {
+            let (tag, value) = if self.is_64_bit {
+                (self.read_u64(offset)?, self.read_u64(offset + 8)?)
+            } else {
+                (self.read_u32(offset)? as u64, self.read_u32(offset + 4)? as u64)
+            };
+            match tag {
+                DT_NULL => break,
+                DT_NEEDED => needed_offsets.push(value),
+                DT_STRTAB => strtab_address = Some(value),
+                _ => {}
+            }
+            offset += entry_size;
+        }
+        if needed_offsets.is_empty() {
+            return Ok(Vec::new());
+        }
+        let strtab_offset = match strtab_address {
+            Some(address) => self.address_to_offset(address)?,
+            None => bail!("Dynamic section has no string table"),
+        };
+        needed_offsets.iter()
+            .map(|&name_offset| self.read_string(strtab_offset + name_offset))
+            .collect()
+    }
+
+    fn address_to_offset(&self, address: u64) -> ::Result<u64> {
+        for segment in self.segments.iter().filter(|segment| segment.kind == PT_LOAD) {
+            if address >= segment.vaddr && address < segment.vaddr + segment.filesz {
+                return Ok(address - segment.vaddr + segment.offset);
+            }
+        }
+        bail!("Address {:#x} is not within any loaded segment", address);
+    }
+
+    fn read_bytes(&self, offset: u64, length: usize) -> ::Result<&'a [u8]> {
+        let start = offset as usize;
+        match start.checked_add(length) {
+            Some(end) if end <= self.data.len() => Ok(&self.data[start..end]),
+            _ => bail!("Offset {:#x} is past the end of the file", offset),
+        }
+    }
+
+    fn read_u16(&self, offset: u64) -> ::Result<u16> {
+        let bytes = self.read_bytes(offset, 2)?;
+        Ok(self.read_uint(bytes) as u16)
+    }
+
+    fn read_u32(&self, offset: u64) -> ::Result<u32> {
+        let bytes = self.read_bytes(offset, 4)?;
+        Ok(self.read_uint(bytes) as u32)
+    }
+
+    fn read_u64(&self, offset: u64) -> ::Result<u64> {
+        let bytes = self.read_bytes(offset, 8)?;
+        Ok(self.read_uint(bytes))
+    }
+
+    fn read_uint(&self, bytes: &[u8]) -> u64 {
+        if self.is_big_endian {
+            bytes.iter().fold(0, |value, &byte| (value << 8) | byte as u64)
+        } else {
+            bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64)
+        }
+    }
+
+    fn read_string(&self, offset: u64) -> ::Result<String> {
+        if offset >= self.data.len() as u64 {
+            bail!("Offset {:#x} is past the end of the file", offset);
+        }
+        let rest = &self.data[offset as usize..];
+        match rest.iter().position(|&byte| byte == 0) {
+            Some(length) => Ok(String::from_utf8_lossy(&rest[..length]).into_owned()),
+            None => bail!("Unterminated string at offset {:#x}", offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ElfFile, needed_libraries};
+    use std::env;
+
+    #[test]
+    fn rejects_non_elf_data() {
+        assert!(ElfFile::parse(b"#!/bin/sh\necho hello\n").is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn finds_libc_in_test_binary() {
+        let libraries = needed_libraries(&env::current_exe().unwrap()).unwrap();
+        assert!(libraries.iter().any(|library| library.starts_with("libc.so")),
+                "{:?}", libraries);
+    }
+}
@@ -0,0 +1,170 @@
+// A minimal ICO encoder, for building the app icon that Windows installers
+// and shortcuts use.
+//
+// An ICO file consists of a small header, followed by a directory with one
+// entry per image, followed by the image data itself.  Each image is stored
+// either as a PNG file (which Windows Vista and later support, and which is
+// the only sensible option for 256x256 images), or as a headerless BMP: a
+// BITMAPINFOHEADER whose height is doubled, then 32-bit BGRA pixels, then a
+// 1-bit transparency mask, with both sets of rows stored bottom-up.
+//
+// For more information about the format, see
+// https://docs.microsoft.com/en-us/previous-versions/ms997538(v=msdn.10)
+
+use image::{self, GenericImage};
+use image::png::PNGEncoder;
+use std::io::Write;
+
+// Images at least this big are stored as PNG data rather than as a BMP:
+const PNG_MIN_SIZE: u32 = 256;
+// The largest image size that an ICO file can hold:
+const MAX_SIZE: u32 = 256;
+
+const ICONDIR_SIZE: u32 = 6;
+const ICONDIRENTRY_SIZE: u32 = 16;
+const BITMAPINFOHEADER_SIZE: u32 = 40;
+
+/// Writes an ICO file containing the given square images, which must each be
+/// between 1 and 256 pixels wide.
+pub fn write_ico<W: Write>(images: &[image::DynamicImage], mut writer: W) -> ::Result<()> {
+    let mut entries = Vec::new();
+    for image in images.iter() {
+        let (width, height) = image.dimensions();
+        if width != height || width == 0 || width > MAX_SIZE {
+            bail!("Invalid size for an ICO image: {}x{}", width, height);
+        }
+        let data = if width >= PNG_MIN_SIZE { encode_png(image)? } else { encode_bmp(image) };
+        entries.push((width, data));
+    }
+
+    write_u16(&mut writer, 0)?; // Reserved
+    write_u16(&mut writer, 1)?; // Image type (1 = icon)
+    write_u16(&mut writer, entries.len() as u16)?;
+    let mut offset = ICONDIR_SIZE + ICONDIRENTRY_SIZE * entries.len() as u32;
+    for &(size, ref data) in entries.iter() {
+        // A size of 256 is stored as zero.
+        let size_byte = if size >= 256 { 0 } else { size as u8 };
+        writer.write_all(&[size_byte, size_byte, 0, 0])?;
+        write_u16(&mut writer, 1)?; // Color planes
+        write_u16(&mut writer, 32)?; // Bits per pixel
+        write_u32(&mut writer, data.len() as u32)?;
+        write_u32(&mut writer, offset)?;
+        offset += data.len() as u32;
+    }
+    for (_, data) in entries.iter() {
+        writer.write_all(data)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn encode_png(image: &image::DynamicImage) -> ::Result<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let mut data = Vec::new();
+    PNGEncoder::new(&mut data).encode(&image.to_rgba().into_raw(), width, height,
+                                      image::ColorType::RGBA(8))?;
+    Ok(data)
+}
+
+fn encode_bmp(image: &image::DynamicImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let rgba = image.to_rgba();
+    // Each row of the mask is padded out to a multiple of 32 bits.
+    let mask_row_size = (width.div_ceil(32) * 4) as usize;
+    let pixels_size = width * height * 4;
+    let mask_size = mask_row_size as u32 * height;
+    let mut data = Vec::with_capacity((BITMAPINFOHEADER_SIZE + pixels_size + mask_size) as usize);
+    {
+        let mut header = |value: u32, bytes: usize| {
+            data.extend_from_slice(&le_bytes(value)[..bytes]);
+        };
+        header(BITMAPINFOHEADER_SIZE, 4);
+        header(width, 4);
+        header(height * 2, 4); // Pixel rows plus mask rows
+        header(1, 2); // Color planes
+        header(32, 2); // Bits per pixel
+        header(0, 4); // Compression (none)
+        header(pixels_size + mask_size, 4);
+        header(0, 4); // Horizontal resolution
+        header(0, 4); // Vertical resolution
+        header(0, 4); // Palette size
+        header(0, 4); // Important colors
+    }
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let pixel = rgba.get_pixel(x, y).data;
+            data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; mask_row_size];
+        for x in 0..width {
+            // Fully transparent pixels are masked out.
+            if rgba.get_pixel(x, y).data[3] == 0 {
+                row[(x / 8) as usize] |= 0x80 >> (x % 8);
+            }
+        }
+        data.extend_from_slice(&row);
+    }
+    data
+}
+
+fn le_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> ::Result<()> {
+    writer.write_all(&le_bytes(value as u32)[..2])?;
+    Ok(())
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> ::Result<()> {
+    writer.write_all(&le_bytes(value))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_ico;
+    use image;
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        data[offset] as u16 | (data[offset + 1] as u16) << 8
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        read_u16(data, offset) as u32 | (read_u16(data, offset + 2) as u32) << 16
+    }
+
+    #[test]
+    fn encodes_bmp_and_png_entries() {
+        let images = vec![image::DynamicImage::ImageRgba8(image::RgbaImage::new(16, 16)),
+                          image::DynamicImage::ImageRgba8(image::RgbaImage::new(256, 256))];
+        let mut data = Vec::new();
+        write_ico(&images, &mut data).unwrap();
+        assert_eq!(read_u16(&data, 2), 1);
+        assert_eq!(read_u16(&data, 4), 2);
+
+        // The 16x16 image is a BMP with a doubled height and a 32-bit mask row.
+        assert_eq!(data[6], 16);
+        let bmp_size = read_u32(&data, 14) as usize;
+        let bmp_offset = read_u32(&data, 18) as usize;
+        assert_eq!(bmp_offset, 6 + 2 * 16);
+        assert_eq!(bmp_size, 40 + 16 * 16 * 4 + 16 * 4);
+        assert_eq!(read_u32(&data, bmp_offset), 40);
+        assert_eq!(read_u32(&data, bmp_offset + 8), 32);
+
+        // The 256x256 image is a PNG, and its size is stored as zero.
+        assert_eq!(data[22], 0);
+        let png_offset = read_u32(&data, 34) as usize;
+        assert_eq!(png_offset, bmp_offset + bmp_size);
+        assert_eq!(&data[png_offset..png_offset + 8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(data.len(), png_offset + read_u32(&data, 30) as usize);
+    }
+
+    #[test]
+    fn rejects_non_square_images() {
+        let images = vec![image::DynamicImage::ImageRgba8(image::RgbaImage::new(16, 32))];
+        assert!(write_ico(&images, Vec::new()).is_err());
+    }
+}
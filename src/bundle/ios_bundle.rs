@@ -9,7 +9,7 @@
 // explanation.
 
 use super::common;
-use {ResultExt, Settings};
+use {PackageType, ResultExt, Settings};
 use icns;
 use image::{self, GenericImage, ImageDecoder};
 use image::png::{PNGDecoder, PNGEncoder};
@@ -34,9 +34,8 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
         format!("Failed to create bundle directory at {:?}", bundle_dir)
     })?;
 
-    for src in settings.resource_files() {
-        let src = src?;
-        let dest = bundle_dir.join(common::resource_relpath(&src));
+    for (src, dest) in settings.resource_files(PackageType::IosBundle)? {
+        let dest = bundle_dir.join(dest);
         common::copy_file(&src, &dest).chain_err(|| {
             format!("Failed to copy resource file {:?}", src)
         })?;
@@ -131,8 +130,9 @@ fn generate_info_plist(bundle_dir: &Path, settings: &Settings, icon_filenames: &
     write!(file, "  <key>CFBundleDisplayName</key>\n  <string>{}</string>\n", settings.bundle_name())?;
     write!(file, "  <key>CFBundleName</key>\n  <string>{}</string>\n", settings.bundle_name())?;
     write!(file, "  <key>CFBundleExecutable</key>\n  <string>{}</string>\n", settings.binary_name())?;
-    write!(file, "  <key>CFBundleVersion</key>\n  <string>{}</string>\n", settings.version_string())?;
-    write!(file, "  <key>CFBundleShortVersionString</key>\n  <string>{}</string>\n", settings.version_string())?;
+    let version = settings.version()?.short_version_string()?;
+    write!(file, "  <key>CFBundleVersion</key>\n  <string>{}</string>\n", version)?;
+    write!(file, "  <key>CFBundleShortVersionString</key>\n  <string>{}</string>\n", version)?;
     write!(file, "  <key>CFBundleDevelopmentRegion</key>\n  <string>en_US</string>\n")?;
     write!(file, "  <key>UILaunchStoryboardName</key>\n  <string></string>\n")?;
 
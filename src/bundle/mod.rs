@@ -1,14 +1,22 @@
+mod appimage_bundle;
+mod archive_bundle;
 mod category;
 mod common;
 mod deb_bundle;
+mod elf;
+mod ico;
 mod ios_bundle;
 mod msi_bundle;
+mod msi_ui;
+mod msi_validate;
 mod osx_bundle;
 mod rpm_bundle;
 mod settings;
+mod squashfs;
+mod version;
 
 pub use self::common::{print_error, print_finished};
-pub use self::settings::{BuildArtifact, PackageType, Settings};
+pub use self::settings::{BuildArtifact, DebCompression, PackageType, Settings};
 use std::path::PathBuf;
 
 pub fn bundle_project(settings: Settings) -> ::Result<Vec<PathBuf>> {
@@ -20,6 +28,8 @@ pub fn bundle_project(settings: Settings) -> ::Result<Vec<PathBuf>> {
             PackageType::WindowsMsi => msi_bundle::bundle_project(&settings)?,
             PackageType::Deb => deb_bundle::bundle_project(&settings)?,
             PackageType::Rpm => rpm_bundle::bundle_project(&settings)?,
+            PackageType::AppImage => appimage_bundle::bundle_project(&settings)?,
+            PackageType::Archive => archive_bundle::bundle_project(&settings)?,
         });
     }
     Ok(paths)
@@ -1,14 +1,20 @@
 use ResultExt;
-use super::common;
-use super::settings::Settings;
+use super::{common, ico, msi_ui, msi_validate};
+use super::settings::{MsiInstallScope, PackageType, Settings};
 use cab;
+use cfb;
+use icns;
+use image::{self, GenericImage};
 use msi;
 use std;
+use std::cmp::min;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use time;
 use uuid::Uuid;
 
 type Package = msi::Package<fs::File>;
@@ -20,12 +26,169 @@ const CABINET_MAX_FILES: usize = 1000;
 // The maximum number of data bytes we'll put in one cabinet:
 const CABINET_MAX_SIZE: u64 = 0x1000_0000;
 
+// The earliest timestamp (1980-01-01) that a cabinet file entry can hold:
+const CABINET_MIN_TIMESTAMP: u64 = 315_532_800;
+
+// The difference between the Windows FILETIME epoch (1601-01-01) and the Unix
+// epoch, in seconds:
+const FILETIME_EPOCH_OFFSET: u64 = 11_644_473_600;
+// Compound file layout: the directory is a chain of sectors of 128-byte
+// entries, each with creation and modification times at offset 100; a
+// sector number past this one marks the end of a chain:
+const CFB_DIR_ENTRY_SIZE: usize = 128;
+const CFB_DIR_ENTRY_TIMES_OFFSET: usize = 100;
+const CFB_MAX_REGULAR_SECTOR: u32 = 0xffff_fffa;
+
+// The summary information stream, which holds a property set whose only
+// section starts at the offset stored at byte 44:
+const SUMMARY_INFO_STREAM: &str = "\u{5}SummaryInformation";
+const SUMMARY_INFO_SECTION_OFFSET_POSITION: usize = 44;
+// The summary info's word count property, and the property type it's stored
+// as (a 32-bit integer):
+const PID_WORDCOUNT: u32 = 15;
+const VT_I4: u32 = 3;
+// Summary info word count flags, indicating that the package's files are
+// compressed (in cabinets), and that installing it doesn't require elevated
+// privileges:
+const WORD_COUNT_COMPRESSED: i32 = 0x2;
+const WORD_COUNT_NO_ELEVATION: i32 = 0x8;
+
 // File table attribute indicating that a file is "vital":
 const FILE_ATTR_VITAL: u16 = 0x200;
 
-// The name of the installer package's sole Feature:
+// Component table attribute indicating that the component's KeyPath is a key
+// in the Registry table, rather than in the File table:
+const COMPONENT_ATTR_REGISTRY_KEY_PATH: i32 = 0x4;
+// Component table attribute indicating that the component is 64-bit, so that
+// its files and registry keys don't get redirected to the 32-bit locations:
+const COMPONENT_ATTR_64_BIT: i32 = 0x100;
+
+// Registry table roots, by their usual abbreviations:
+const REGISTRY_ROOT_CLASSES: i32 = 0;
+const REGISTRY_ROOT_CURRENT_USER: i32 = 1;
+const REGISTRY_ROOTS: &[(&str, i32)] = &[
+    ("HKCR", REGISTRY_ROOT_CLASSES),
+    ("HKCU", REGISTRY_ROOT_CURRENT_USER),
+    ("HKLM", 2),
+    ("HKU", 3),
+];
+
+// The Component that the main executable is part of, which registry entries
+// and environment variables from the settings are tied to:
+const MAIN_COMPONENT_KEY: &str = "INSTALLDIR";
+
+// The sizes (in pixels) of the images in the app icon, when it has to be
+// generated from PNG or ICNS files:
+const ICON_SIZES: &[u32] = &[16, 24, 32, 48, 64, 128, 256];
+
+// The name of the installer package's main Feature, which holds everything
+// that isn't part of an optional feature from the settings:
 const MAIN_FEATURE_NAME: &str = "MainFeature";
 
+// Feature table attributes:
+const FEATURE_ATTR_UI_DISALLOW_ABSENT: i32 = 0x10;
+
+// Standard actions (and their sequence numbers) for the InstallUISequence
+// table, which is used when the installer runs with a full or reduced UI:
+const INSTALL_UI_SEQUENCE: &[(&str, i32)] = &[
+    ("FindRelatedProducts", 25),
+    ("LaunchConditions", 100),
+    ("ValidateProductID", 700),
+    ("CostInitialize", 800),
+    ("FileCost", 900),
+    ("CostFinalize", 1000),
+    ("MigrateFeatureStates", 1200),
+    ("ExecuteAction", 1300),
+];
+
+// Standard actions (and their sequence numbers) for the
+// InstallExecuteSequence table, which does the actual installation work:
+const INSTALL_EXECUTE_SEQUENCE: &[(&str, i32)] = &[
+    ("FindRelatedProducts", 25),
+    ("LaunchConditions", 100),
+    ("ValidateProductID", 700),
+    ("CostInitialize", 800),
+    ("FileCost", 900),
+    ("CostFinalize", 1000),
+    ("MigrateFeatureStates", 1200),
+    ("InstallValidate", 1400),
+    ("RemoveExistingProducts", 1401),
+    ("InstallInitialize", 1500),
+    ("ProcessComponents", 1600),
+    ("UnpublishFeatures", 1800),
+    ("RemoveRegistryValues", 2600),
+    ("UnregisterExtensionInfo", 2800),
+    ("UnregisterProgIdInfo", 2900),
+    ("UnregisterMIMEInfo", 3000),
+    ("RemoveShortcuts", 3200),
+    ("RemoveEnvironmentStrings", 3300),
+    ("RemoveFiles", 3500),
+    ("RemoveFolders", 3600),
+    ("CreateFolders", 3700),
+    ("InstallFiles", 4000),
+    ("CreateShortcuts", 4500),
+    ("RegisterExtensionInfo", 4700),
+    ("RegisterProgIdInfo", 4800),
+    ("RegisterMIMEInfo", 4900),
+    ("WriteRegistryValues", 5000),
+    ("WriteEnvironmentStrings", 5200),
+    ("RegisterUser", 6000),
+    ("RegisterProduct", 6100),
+    ("PublishFeatures", 6300),
+    ("PublishProduct", 6400),
+    ("InstallFinalize", 6600),
+];
+
+// Standard actions (and their sequence numbers) for the AdminUISequence
+// table, used when creating an administrative installation point:
+const ADMIN_UI_SEQUENCE: &[(&str, i32)] = &[
+    ("CostInitialize", 800),
+    ("FileCost", 900),
+    ("CostFinalize", 1000),
+    ("ExecuteAction", 1300),
+];
+
+// Standard actions (and their sequence numbers) for the
+// AdminExecuteSequence table:
+const ADMIN_EXECUTE_SEQUENCE: &[(&str, i32)] = &[
+    ("CostInitialize", 800),
+    ("FileCost", 900),
+    ("CostFinalize", 1000),
+    ("InstallValidate", 1400),
+    ("InstallInitialize", 1500),
+    ("InstallAdminPackage", 3900),
+    ("InstallFiles", 4000),
+    ("InstallFinalize", 6600),
+];
+
+// Standard actions (and their sequence numbers) for the AdvtExecuteSequence
+// table, used when the product is advertised:
+const ADVT_EXECUTE_SEQUENCE: &[(&str, i32)] = &[
+    ("CostInitialize", 800),
+    ("CostFinalize", 1000),
+    ("InstallValidate", 1400),
+    ("InstallInitialize", 1500),
+    ("CreateShortcuts", 4500),
+    ("RegisterClassInfo", 4600),
+    ("RegisterExtensionInfo", 4700),
+    ("RegisterProgIdInfo", 4800),
+    ("RegisterMIMEInfo", 4900),
+    ("PublishFeatures", 6300),
+    ("PublishProduct", 6400),
+    ("InstallFinalize", 6600),
+];
+
+// Upgrade table attribute indicating that the installer should only detect
+// related products, rather than removing them:
+const UPGRADE_ATTR_ONLY_DETECT: i32 = 0x2;
+// Upgrade table attribute indicating that VersionMax is an inclusive bound:
+const UPGRADE_ATTR_VERSION_MAX_INCLUSIVE: i32 = 0x200;
+
+// Properties set by FindRelatedProducts when an older or newer version of
+// the product is already installed:
+const OLDER_VERSION_PROPERTY: &str = "OLDERVERSIONDETECTED";
+const NEWER_VERSION_PROPERTY: &str = "NEWERVERSIONDETECTED";
+
 // A v4 UUID that was generated specifically for cargo-bundle, to be used as a
 // namespace for generating v5 UUIDs from bundle identifier strings.
 const UUID_NAMESPACE: [u8; 16] = [
@@ -33,6 +196,62 @@ const UUID_NAMESPACE: [u8; 16] = [
     0xa6, 0x16, 0x76, 0x14, 0x8d, 0xfa, 0x0c, 0x7b,
 ];
 
+// The processor architectures that MSI packages can target.
+#[derive(Clone, Copy)]
+enum Platform {
+    X86,
+    X64,
+    Arm64,
+}
+
+impl Platform {
+    // Returns the platform that the binary being bundled was built for.
+    fn from_settings(settings: &Settings) -> ::Result<Platform> {
+        match settings.binary_arch() {
+            "x86" => Ok(Platform::X86),
+            "x86_64" => Ok(Platform::X64),
+            "aarch64" => Ok(Platform::Arm64),
+            other => bail!("Unsupported architecture for MSI packages: {}", other),
+        }
+    }
+
+    // The name of this platform in the summary info's Template property.
+    fn template_name(self) -> &'static str {
+        match self {
+            Platform::X86 => "Intel",
+            Platform::X64 => "x64",
+            Platform::Arm64 => "Arm64",
+        }
+    }
+
+    fn is_64_bit(self) -> bool {
+        match self {
+            Platform::X86 => false,
+            Platform::X64 | Platform::Arm64 => true,
+        }
+    }
+
+    // The Directory table key of the Program Files folder that per-machine
+    // installs of apps for this platform go under.
+    fn program_files_folder(self) -> &'static str {
+        if self.is_64_bit() { "ProgramFiles64Folder" } else { "ProgramFilesFolder" }
+    }
+
+    // The Component table attributes that every component should have.
+    fn component_attributes(self) -> i32 {
+        if self.is_64_bit() { COMPONENT_ATTR_64_BIT } else { 0 }
+    }
+}
+
+// Returns the Directory table key of the folder that the install dir goes
+// under: Program Files, or for per-user installs, %LOCALAPPDATA%\Programs.
+fn install_root_folder(platform: Platform, scope: MsiInstallScope) -> &'static str {
+    match scope {
+        MsiInstallScope::User => "LocalProgramsFolder",
+        MsiInstallScope::Machine | MsiInstallScope::Both => platform.program_files_folder(),
+    }
+}
+
 // Info about a resource file (including the main executable) in the bundle.
 struct ResourceInfo {
     // The path to the existing file that will be bundled as a resource.
@@ -43,6 +262,8 @@ struct ResourceInfo {
     filename: String,
     // The size of this resource file, in bytes.
     size: u64,
+    // The name of the Feature that this resource is part of.
+    feature: String,
     // The database key for the Component that this resource is part of.
     component_key: String,
 }
@@ -55,7 +276,18 @@ struct DirectoryInfo {
     parent_key: String,
     // The name of this directory in the filesystem.
     name: String,
-    // List of files in this directory, not counting subdirectories.
+}
+
+// Info about a Component: the resource files in one directory (not counting
+// subdirectories) that are part of the same Feature.
+struct ComponentInfo {
+    // The database key for this component.
+    key: String,
+    // The database key for the directory that this component's files are in.
+    directory_key: String,
+    // The name of the Feature that this component is part of.
+    feature: String,
+    // List of the files in this component.
     files: Vec<String>,
 }
 
@@ -79,9 +311,13 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
     })?;
 
     // Generate package metadata:
-    let guid = generate_package_guid(settings);
-    set_summary_info(&mut package, guid, settings);
-    create_property_table(&mut package, guid, settings).chain_err(|| {
+    let platform = Platform::from_settings(settings)?;
+    let upgrade_code = get_upgrade_code(settings)?;
+    let version = settings.version()?.msi_version()?;
+    let product_code = generate_product_code(upgrade_code, &version);
+    set_summary_info(&mut package, product_code, platform, settings);
+    create_property_table(&mut package, product_code, upgrade_code, &version,
+                          settings).chain_err(|| {
         "Failed to generate Property table"
     })?;
 
@@ -89,25 +325,28 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
     let mut resources = collect_resource_info(settings).chain_err(|| {
         "Failed to collect resource file information"
     })?;
-    let directories = collect_directory_info(settings, &mut resources).chain_err(|| {
+    let (directories, components) = collect_directory_info(settings, platform,
+                                                           &mut resources).chain_err(|| {
         "Failed to collect resource directory information"
     })?;
     let cabinets = divide_resources_into_cabinets(resources);
-    generate_resource_cabinets(&mut package, &cabinets).chain_err(|| {
+    generate_resource_cabinets(&mut package, &cabinets, settings.source_date_epoch()).chain_err(|| {
         "Failed to generate resource cabinets"
     })?;
 
     // Set up installer database tables:
-    create_directory_table(&mut package, &directories).chain_err(|| {
+    create_directory_table(&mut package, platform, settings.msi_install_scope(),
+                           &directories).chain_err(|| {
         "Failed to generate Directory table"
     })?;
     create_feature_table(&mut package, settings).chain_err(|| {
         "Failed to generate Feature table"
     })?;
-    create_component_table(&mut package, guid, &directories).chain_err(|| {
+    create_component_table(&mut package, upgrade_code, platform, &components,
+                           &cabinets).chain_err(|| {
         "Failed to generate Component table"
     })?;
-    create_feature_components_table(&mut package, &directories).chain_err(|| {
+    create_feature_components_table(&mut package, &components).chain_err(|| {
         "Failed to generate FeatureComponents table"
     })?;
     create_media_table(&mut package, &cabinets).chain_err(|| {
@@ -116,25 +355,82 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
     create_file_table(&mut package, &cabinets).chain_err(|| {
         "Failed to generate File table"
     })?;
-    // TODO: Create other needed tables.
+    create_sequence_tables(&mut package).chain_err(|| {
+        "Failed to generate sequence tables"
+    })?;
+    create_upgrade_table(&mut package, upgrade_code, &version).chain_err(|| {
+        "Failed to generate Upgrade table"
+    })?;
+    create_launch_condition_table(&mut package).chain_err(|| {
+        "Failed to generate LaunchCondition table"
+    })?;
 
     // Create app icon:
     package.create_table("Icon", vec![
         msi::Column::build("Name").primary_key().id_string(72),
         msi::Column::build("Data").binary(),
     ])?;
-    let icon_name = format!("{}.ico", settings.binary_name());
-    {
+    let mut icon_data = Vec::new();
+    let icon_name = if create_app_icon(&mut icon_data, settings)? {
+        let icon_name = format!("{}.ico", settings.binary_name());
         let stream_name = format!("Icon.{}", icon_name);
-        let mut stream = package.write_stream(&stream_name)?;
-        create_app_icon(&mut stream, settings)?;
+        package.write_stream(&stream_name)?.write_all(&icon_data)?;
+        package.insert_rows(msi::Insert::into("Icon").row(vec![
+            msi::Value::Str(icon_name.clone()),
+            msi::Value::from("Name"),
+        ]))?;
+        Some(icon_name)
+    } else {
+        None
+    };
+    let icon_name = icon_name.as_deref();
+    add_arp_properties(&mut package, icon_name, settings).chain_err(|| {
+        "Failed to add Add/Remove Programs properties"
+    })?;
+
+    // Create registry entries, environment variables and shortcuts:
+    create_registry_table(&mut package, settings).chain_err(|| {
+        "Failed to generate Registry table"
+    })?;
+    create_environment_table(&mut package, settings).chain_err(|| {
+        "Failed to generate Environment table"
+    })?;
+    create_shortcut_table(&mut package, upgrade_code, platform, icon_name,
+                          settings).chain_err(|| {
+        "Failed to generate Shortcut table"
+    })?;
+    create_association_tables(&mut package, icon_name, settings).chain_err(|| {
+        "Failed to generate file association tables"
+    })?;
+
+    // Create the installer UI:
+    if settings.msi_ui() {
+        let license_rtf = match settings.license_file() {
+            Some(path) => Some(read_license_rtf(path)?),
+            None => None,
+        };
+        msi_ui::create_ui_tables(&mut package, license_rtf.as_deref(),
+                                 !settings.msi_features().is_empty())
+            .chain_err(|| "Failed to generate installer UI tables")?;
     }
-    package.insert_rows(msi::Insert::into("Icon").row(vec![
-        msi::Value::Str(icon_name.clone()),
-        msi::Value::from("Name"),
-    ]))?;
 
     package.flush()?;
+    drop(package);
+    let word_count = match settings.msi_install_scope() {
+        MsiInstallScope::User => WORD_COUNT_COMPRESSED | WORD_COUNT_NO_ELEVATION,
+        MsiInstallScope::Machine | MsiInstallScope::Both => WORD_COUNT_COMPRESSED,
+    };
+    set_summary_word_count(&msi_path, word_count).chain_err(|| {
+        format!("Failed to set the summary information word count in {}", msi_name)
+    })?;
+    if let Some(epoch) = settings.source_date_epoch() {
+        set_compound_file_timestamps(&msi_path, epoch).chain_err(|| {
+            format!("Failed to set timestamps in {}", msi_name)
+        })?;
+    }
+    msi_validate::validate_package(&msi_path).chain_err(|| {
+        format!("Generated {} is invalid", msi_name)
+    })?;
     Ok(vec![msi_path])
 }
 
@@ -152,19 +448,177 @@ fn new_empty_package(msi_path: &Path) -> ::Result<Package> {
     Ok(package)
 }
 
-// Generates a GUID for the package, based on `settings.bundle_identifier()`.
-fn generate_package_guid(settings: &Settings) -> Uuid {
-    let namespace = Uuid::from_bytes(UUID_NAMESPACE);
-    Uuid::new_v5(&namespace, &settings.bundle_identifier().as_bytes())
+// Sets the word count property of the package's summary information, which
+// the msi crate has no setter for, by rewriting the summary information
+// stream in the package's compound file.
+fn set_summary_word_count(msi_path: &Path, word_count: i32) -> ::Result<()> {
+    let mut compound_file = cfb::open_rw(msi_path)?;
+    let mut data = Vec::new();
+    compound_file.open_stream(SUMMARY_INFO_STREAM)?.read_to_end(&mut data)?;
+    let read_u32 = |data: &[u8], offset: usize| -> ::Result<u32> {
+        match data.get(offset..offset + 4) {
+            Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            None => bail!("Summary information stream is truncated"),
+        }
+    };
+    let section = read_u32(&data, SUMMARY_INFO_SECTION_OFFSET_POSITION)? as usize;
+    let section_size = read_u32(&data, section)? as usize;
+    let num_properties = read_u32(&data, section + 4)? as usize;
+    if data.len() < section + section_size {
+        bail!("Summary information stream is truncated");
+    }
+    let mut value = VT_I4.to_le_bytes().to_vec();
+    value.extend_from_slice(&word_count.to_le_bytes());
+
+    // If the property is already there, replace its value in place.
+    // Otherwise, add it to the end of the property list (moving every
+    // property value along to make room) and append its value to the end of
+    // the section.
+    for index in 0..num_properties {
+        let entry = section + 8 + 8 * index;
+        if read_u32(&data, entry)? == PID_WORDCOUNT {
+            let offset = section + read_u32(&data, entry + 4)? as usize;
+            if data.len() < offset + value.len() {
+                bail!("Summary information stream is truncated");
+            }
+            data[offset..offset + value.len()].copy_from_slice(&value);
+            return write_summary_info(&mut compound_file, &data);
+        }
+    }
+    let mut section_data = data[section..section + section_size].to_vec();
+    let new_section_size = (section_size + 8 + value.len()) as u32;
+    section_data[0..4].copy_from_slice(&new_section_size.to_le_bytes());
+    section_data[4..8].copy_from_slice(&(num_properties as u32 + 1).to_le_bytes());
+    for index in 0..num_properties {
+        let entry = 8 + 8 * index + 4;
+        let offset = read_u32(&section_data, entry)? + 8;
+        section_data[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+    let entries_end = 8 + 8 * num_properties;
+    let mut entry = PID_WORDCOUNT.to_le_bytes().to_vec();
+    entry.extend_from_slice(&(section_size as u32 + 8).to_le_bytes());
+    section_data.splice(entries_end..entries_end, entry);
+    section_data.extend_from_slice(&value);
+    data.splice(section..section + section_size, section_data);
+    write_summary_info(&mut compound_file, &data)
+}
+
+fn write_summary_info(compound_file: &mut cfb::CompoundFile<fs::File>, data: &[u8])
+                      -> ::Result<()> {
+    let mut stream = compound_file.create_stream(SUMMARY_INFO_STREAM)?;
+    stream.write_all(data)?;
+    stream.flush()?;
+    drop(stream);
+    compound_file.flush()?;
+    Ok(())
+}
+
+// Sets the creation and modification times of every stream and storage in
+// the package's compound file that has them to the given Unix time.  The msi
+// crate always stamps them with the current time, so this is needed for
+// reproducible packages.
+fn set_compound_file_timestamps(msi_path: &Path, unix_time: u64) -> ::Result<()> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(msi_path)?;
+    let mut header = [0u8; 512];
+    file.read_exact(&mut header)?;
+    let read_u32 = |bytes: &[u8], offset: usize| {
+        u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2],
+                            bytes[offset + 3]])
+    };
+    let sector_shift = u16::from_le_bytes([header[30], header[31]]);
+    if sector_shift != 9 && sector_shift != 12 {
+        bail!("Unsupported compound file sector shift: {}", sector_shift);
+    }
+    let sector_size = 1usize << sector_shift;
+    let sector_offset = |sector: u32| (u64::from(sector) + 1) << sector_shift;
+    let read_sector = |file: &mut fs::File, sector: u32| -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0u8; sector_size];
+        file.seek(SeekFrom::Start(sector_offset(sector)))?;
+        file.read_exact(&mut buffer)?;
+        Ok(buffer)
+    };
+
+    // Collect the FAT's sectors: the first 109 are listed in the header, and
+    // the rest in a chain of DIFAT sectors.
+    let num_fat_sectors = read_u32(&header, 44) as usize;
+    let mut fat_sectors: Vec<u32> = (0..109).map(|index| read_u32(&header, 76 + 4 * index))
+        .take(num_fat_sectors).collect();
+    let mut difat_sector = read_u32(&header, 68);
+    while fat_sectors.len() < num_fat_sectors && difat_sector <= CFB_MAX_REGULAR_SECTOR {
+        let difat = read_sector(&mut file, difat_sector)?;
+        let entries_per_sector = sector_size / 4 - 1;
+        fat_sectors.extend((0..entries_per_sector).map(|index| read_u32(&difat, 4 * index))
+                           .take(num_fat_sectors - fat_sectors.len()));
+        difat_sector = read_u32(&difat, 4 * entries_per_sector);
+    }
+    let mut fat = Vec::with_capacity(fat_sectors.len() * sector_size / 4);
+    for &sector in fat_sectors.iter() {
+        let data = read_sector(&mut file, sector)?;
+        fat.extend((0..sector_size / 4).map(|index| read_u32(&data, 4 * index)));
+    }
+
+    // Walk the directory's chain of sectors, rewriting each nonzero time.
+    let filetime = (unix_time + FILETIME_EPOCH_OFFSET) * 10_000_000;
+    let mut dir_sector = read_u32(&header, 48);
+    let mut visited = HashSet::new();
+    while dir_sector <= CFB_MAX_REGULAR_SECTOR {
+        if !visited.insert(dir_sector) || dir_sector as usize >= fat.len() {
+            bail!("Invalid compound file directory chain");
+        }
+        let mut data = read_sector(&mut file, dir_sector)?;
+        for entry in data.chunks_mut(CFB_DIR_ENTRY_SIZE) {
+            for time in entry[CFB_DIR_ENTRY_TIMES_OFFSET..CFB_DIR_ENTRY_TIMES_OFFSET + 16]
+                .chunks_mut(8)
+            {
+                if time.iter().any(|&byte| byte != 0) {
+                    time.copy_from_slice(&filetime.to_le_bytes());
+                }
+            }
+        }
+        file.seek(SeekFrom::Start(sector_offset(dir_sector)))?;
+        file.write_all(&data)?;
+        dir_sector = fat[dir_sector as usize];
+    }
+    file.flush()?;
+    Ok(())
+}
+
+// Returns the UpgradeCode for the package, which stays the same across all
+// versions of the app so that newer versions can replace older ones.  This is
+// either given by the `msi_upgrade_code` setting, or else generated from
+// `settings.bundle_identifier()`.
+fn get_upgrade_code(settings: &Settings) -> ::Result<Uuid> {
+    match settings.msi_upgrade_code() {
+        Some(code) => Uuid::parse_str(code).chain_err(|| {
+            format!("Invalid msi_upgrade_code {:?}", code)
+        }),
+        None => {
+            let namespace = Uuid::from_bytes(UUID_NAMESPACE);
+            Ok(Uuid::new_v5(&namespace, settings.bundle_identifier().as_bytes()))
+        }
+    }
+}
+
+// Generates the ProductCode for the package, which is different for each
+// version of the app.  It's derived from the MSI version (rather than the
+// app's own version string) so that two versions that get the same
+// ProductVersion, like 1.2.3-beta.1 and 1.2.3, also get the same ProductCode.
+fn generate_product_code(upgrade_code: Uuid, msi_version: &str) -> Uuid {
+    Uuid::new_v5(&upgrade_code, msi_version.as_bytes())
 }
 
 // Populates the summary metadata for the package from the bundle settings.
-fn set_summary_info(package: &mut Package, package_guid: Uuid,
-                    settings: &Settings) {
+fn set_summary_info(package: &mut Package, product_code: Uuid,
+                    platform: Platform, settings: &Settings) {
     let summary_info = package.summary_info_mut();
-    summary_info.set_creation_time_to_now();
+    match settings.source_date_epoch() {
+        Some(epoch) => summary_info.set_creation_time(UNIX_EPOCH + Duration::from_secs(epoch)),
+        None => summary_info.set_creation_time_to_now(),
+    }
+    summary_info.set_arch(platform.template_name().to_string());
+    summary_info.set_languages(&[msi::Language::from_tag("en-US")]);
     summary_info.set_subject(settings.bundle_name().to_string());
-    summary_info.set_uuid(package_guid);
+    summary_info.set_uuid(product_code);
     summary_info.set_comments(settings.short_description().to_string());
     if let Some(authors) = settings.authors_comma_separated() {
         summary_info.set_author(authors);
@@ -174,9 +628,13 @@ fn set_summary_info(package: &mut Package, package_guid: Uuid,
 }
 
 // Creates and populates the `Property` database table for the package.
-fn create_property_table(package: &mut Package, package_guid: Uuid,
-                         settings: &Settings) -> ::Result<()> {
-    let authors = settings.authors_comma_separated().unwrap_or(String::new());
+fn create_property_table(package: &mut Package, product_code: Uuid,
+                         upgrade_code: Uuid, version: &str, settings: &Settings)
+                         -> ::Result<()> {
+    // Manufacturer is a required property, so if the package has no authors,
+    // fall back to the app's name.
+    let authors = settings.authors_comma_separated()
+        .unwrap_or_else(|| settings.bundle_name().to_string());
     package.create_table("Property", vec![
         msi::Column::build("Property").primary_key().id_string(72),
         msi::Column::build("Value").text_string(0),
@@ -186,7 +644,7 @@ fn create_property_table(package: &mut Package, package_guid: Uuid,
         msi::Value::Str(authors),
     ]).row(vec![
         msi::Value::from("ProductCode"),
-        msi::Value::from(package_guid),
+        msi::Value::from(product_code),
     ]).row(vec![
         msi::Value::from("ProductLanguage"),
         msi::Value::from(msi::Language::from_tag("en-US")),
@@ -195,13 +653,70 @@ fn create_property_table(package: &mut Package, package_guid: Uuid,
         msi::Value::from(settings.bundle_name()),
     ]).row(vec![
         msi::Value::from("ProductVersion"),
-        msi::Value::from(settings.version_string()),
+        msi::Value::from(version),
+    ]).row(vec![
+        msi::Value::from("UpgradeCode"),
+        msi::Value::from(upgrade_code),
+    ]).row(vec![
+        // The properties set by FindRelatedProducts must be passed from the
+        // UI sequence to the execute sequence.
+        msi::Value::from("SecureCustomProperties"),
+        msi::Value::Str(format!("{};{}", OLDER_VERSION_PROPERTY, NEWER_VERSION_PROPERTY)),
     ]))?;
+    add_install_scope_properties(package, settings.msi_install_scope())
+}
+
+// Adds the properties that control whether the package is installed per-user
+// or per-machine to the `Property` table.
+fn add_install_scope_properties(package: &mut Package, scope: MsiInstallScope)
+                                -> ::Result<()> {
+    // ALLUSERS=1 makes a per-machine install.  ALLUSERS=2 makes a per-user
+    // install if MSIINSTALLPERUSER=1, and otherwise a per-machine one; for
+    // the "both" scope, the user can choose by setting MSIINSTALLPERUSER.
+    let properties: &[(&str, &str)] = match scope {
+        MsiInstallScope::User => &[("ALLUSERS", "2"), ("MSIINSTALLPERUSER", "1")],
+        MsiInstallScope::Machine => &[("ALLUSERS", "1")],
+        MsiInstallScope::Both => &[("ALLUSERS", "2")],
+    };
+    let rows = properties.iter().map(|&(property, value)| {
+        vec![msi::Value::from(property), msi::Value::from(value)]
+    }).collect();
+    package.insert_rows(msi::Insert::into("Property").rows(rows))?;
+    Ok(())
+}
+
+// Adds the properties that control how the app is shown in Add/Remove
+// Programs (or Apps & Features) to the `Property` table: its icon (if it has
+// one), links to its homepage, its authors and its description.
+fn add_arp_properties(package: &mut Package, icon_name: Option<&str>, settings: &Settings)
+                      -> ::Result<()> {
+    let mut rows = vec![
+        // The installer UI only runs for new installs, so there is no
+        // maintenance UI for the Modify button to show.
+        vec![msi::Value::from("ARPNOMODIFY"), msi::Value::from("1")],
+    ];
+    if let Some(icon_name) = icon_name {
+        rows.push(vec![msi::Value::from("ARPPRODUCTICON"), msi::Value::from(icon_name)]);
+    }
+    let homepage_url = settings.homepage_url();
+    if !homepage_url.is_empty() {
+        rows.push(vec![msi::Value::from("ARPHELPLINK"), msi::Value::from(homepage_url)]);
+        rows.push(vec![msi::Value::from("ARPURLINFOABOUT"), msi::Value::from(homepage_url)]);
+    }
+    if let Some(authors) = settings.authors_comma_separated() {
+        rows.push(vec![msi::Value::from("ARPCONTACT"), msi::Value::Str(authors)]);
+    }
+    let description = settings.short_description();
+    if !description.is_empty() {
+        rows.push(vec![msi::Value::from("ARPCOMMENTS"), msi::Value::from(description)]);
+    }
+    package.insert_rows(msi::Insert::into("Property").rows(rows))?;
     Ok(())
 }
 
 // Returns a list of `ResourceInfo` structs for the binary executable and all
-// the resource files that should be included in the package.
+// the resource files that should be included in the package, including those
+// of the package's optional features.
 fn collect_resource_info(settings: &Settings) -> ::Result<Vec<ResourceInfo>> {
     let mut resources = Vec::<ResourceInfo>::new();
     resources.push(ResourceInfo {
@@ -209,45 +724,83 @@ fn collect_resource_info(settings: &Settings) -> ::Result<Vec<ResourceInfo>> {
         dest_path: PathBuf::from(settings.binary_name()),
         filename: settings.binary_name().to_string(),
         size: settings.binary_path().metadata()?.len(),
+        feature: MAIN_FEATURE_NAME.to_string(),
         component_key: String::new(),
     });
+    let mut feature_files = vec![
+        (MAIN_FEATURE_NAME, settings.resource_files(PackageType::WindowsMsi)?),
+    ];
+    for feature in settings.msi_features() {
+        if !is_valid_feature_name(&feature.name) {
+            bail!("Invalid MSI feature name {:?} (feature names must be at most 38 letters, \
+                   digits, underscores or periods, starting with a letter or underscore)",
+                  feature.name);
+        }
+        if feature.name == MAIN_FEATURE_NAME {
+            bail!("The MSI feature name {:?} is reserved", MAIN_FEATURE_NAME);
+        }
+        if feature_files.iter().any(|&(name, _)| name == feature.name) {
+            bail!("More than one MSI feature is named {:?}", feature.name);
+        }
+        let files = settings.msi_feature_resource_files(feature).chain_err(|| {
+            format!("Failed to collect resource files for MSI feature {:?}", feature.name)
+        })?;
+        feature_files.push((feature.name.as_str(), files));
+    }
     let root_rsrc_dir = PathBuf::from("Resources");
-    for source_path in settings.resource_files() {
-        let source_path = source_path?;
-        let metadata = source_path.metadata()?;
-        let size = metadata.len();
-        let dest_path =
-            root_rsrc_dir.join(common::resource_relpath(&source_path));
-        let filename =
-            dest_path.file_name().unwrap().to_string_lossy().to_string();
-        let info = ResourceInfo {
-            source_path,
-            dest_path,
-            filename,
-            size,
-            component_key: String::new(),
-        };
-        resources.push(info);
+    let mut dest_paths = HashSet::<PathBuf>::new();
+    for (feature, files) in feature_files {
+        for (source_path, dest_path) in files {
+            let metadata = source_path.metadata()?;
+            let size = metadata.len();
+            let dest_path = root_rsrc_dir.join(dest_path);
+            if !dest_paths.insert(dest_path.clone()) {
+                bail!("More than one resource file would be placed at {:?}", dest_path);
+            }
+            let filename =
+                dest_path.file_name().unwrap().to_string_lossy().to_string();
+            let info = ResourceInfo {
+                source_path,
+                dest_path,
+                filename,
+                size,
+                feature: feature.to_string(),
+                component_key: String::new(),
+            };
+            resources.push(info);
+        }
     }
     Ok(resources)
 }
 
+// Returns true if `name` can be used as the key of a Feature (an Identifier
+// of at most 38 characters).
+fn is_valid_feature_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {}
+        _ => return false,
+    }
+    name.len() <= 38 && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
 // Based on the list of all resource files to be bundled, returns a list of
-// all the directories that need to be created during installation.  Also,
-// modifies each `ResourceInfo` object to populate its `component_key` field
-// with the database key of the Component that the resource will be associated
-// with.
-fn collect_directory_info(settings: &Settings,
+// all the directories that need to be created during installation, and of
+// the Components that the resources are grouped into (one for each directory
+// and Feature with files in it).  Also, modifies each `ResourceInfo` object to
+// populate its `component_key` field with the database key of the Component
+// that the resource will be associated with.
+fn collect_directory_info(settings: &Settings, platform: Platform,
                           resources: &mut Vec<ResourceInfo>)
-                          -> ::Result<Vec<DirectoryInfo>> {
+                          -> ::Result<(Vec<DirectoryInfo>, Vec<ComponentInfo>)> {
     let mut dir_map = BTreeMap::<PathBuf, DirectoryInfo>::new();
     let mut dir_index: i32 = 0;
     dir_map.insert(PathBuf::new(), DirectoryInfo {
         key: "INSTALLDIR".to_string(),
-        parent_key: "ProgramFilesFolder".to_string(),
+        parent_key: install_root_folder(platform, settings.msi_install_scope()).to_string(),
         name: settings.bundle_name().to_string(),
-        files: Vec::new(),
     });
+    let mut component_map = BTreeMap::<String, ComponentInfo>::new();
     for resource in resources.iter_mut() {
         let mut dir_key = "INSTALLDIR".to_string();
         let mut dir_path = PathBuf::new();
@@ -262,19 +815,32 @@ fn collect_directory_info(settings: &Settings,
                         key: new_key.clone(),
                         parent_key: dir_key.clone(),
                         name: name.to_string_lossy().to_string(),
-                        files: Vec::new(),
                     });
                     dir_key = new_key;
                     dir_index += 1;
                 }
             }
         }
-        let directory = dir_map.get_mut(&dir_path).unwrap();
-        debug_assert_eq!(directory.key, dir_key);
-        directory.files.push(resource.filename.clone());
-        resource.component_key = dir_key.to_string();
+        debug_assert_eq!(dir_map.get(&dir_path).unwrap().key, dir_key);
+        // The main feature's components are keyed by just their directory, as
+        // they always have been, so that their keys stay stable.
+        let component_key = if resource.feature == MAIN_FEATURE_NAME {
+            dir_key.clone()
+        } else {
+            format!("{}_{}", dir_key, resource.feature)
+        };
+        let component = component_map.entry(component_key.clone()).or_insert_with(|| {
+            ComponentInfo {
+                key: component_key.clone(),
+                directory_key: dir_key.clone(),
+                feature: resource.feature.clone(),
+                files: Vec::new(),
+            }
+        });
+        component.files.push(resource.filename.clone());
+        resource.component_key = component_key;
     }
-    Ok(dir_map.into_iter().map(|(_k, v)| v).collect())
+    Ok((dir_map.into_values().collect(), component_map.into_values().collect()))
 }
 
 // Divides up the list of resource into some number of cabinets, subject to a
@@ -314,10 +880,24 @@ fn divide_resources_into_cabinets(mut resources: Vec<ResourceInfo>)
 }
 
 // Creates the CAB archives within the package that contain the binary
-// executable and all the resource files.
+// executable and all the resource files.  If a `source_date_epoch` is given,
+// it is used as the timestamp of every file, in place of the current time.
 fn generate_resource_cabinets(package: &mut Package,
-                              cabinets: &[CabinetInfo])
+                              cabinets: &[CabinetInfo],
+                              source_date_epoch: Option<u64>)
                               -> ::Result<()> {
+    let datetime = match source_date_epoch {
+        Some(epoch) => {
+            let epoch = std::cmp::max(epoch, CABINET_MIN_TIMESTAMP);
+            let datetime = time::OffsetDateTime::from_unix_timestamp(epoch as i64)
+                .chain_err(|| format!("Invalid timestamp: {}", epoch))?;
+            Some(time::PrimitiveDateTime::new(datetime.date(), datetime.time()))
+        }
+        None => None,
+    };
+    // Files are stored in the cabinets under their File table keys, which
+    // are assigned in the same order as in `create_file_table`.
+    let mut sequence: i32 = 1;
     for cabinet_info in cabinets.iter() {
         let mut builder = cab::CabinetBuilder::new();
         let mut file_map = HashMap::<String, &Path>::new();
@@ -330,11 +910,14 @@ fn generate_resource_cabinets(package: &mut Package,
             {
                 let resource = &cabinet_info.resources[resource_index];
                 folder_size += resource.size;
-                folder.add_file(resource.filename.as_str());
-                debug_assert!(!file_map.contains_key(&resource.filename));
-                file_map.insert(resource.filename.clone(),
-                                &resource.source_path);
+                let key = file_key(sequence);
+                let file = folder.add_file(key.as_str());
+                if let Some(datetime) = datetime {
+                    file.set_datetime(datetime);
+                }
+                file_map.insert(key, &resource.source_path);
                 resource_index += 1;
+                sequence += 1;
             }
         }
         let stream = package.write_stream(cabinet_info.name.as_str())?;
@@ -351,7 +934,8 @@ fn generate_resource_cabinets(package: &mut Package,
 }
 
 // Creates and populates the `Directory` database table for the package.
-fn create_directory_table(package: &mut Package, directories: &[DirectoryInfo])
+fn create_directory_table(package: &mut Package, platform: Platform,
+                          scope: MsiInstallScope, directories: &[DirectoryInfo])
                           -> ::Result<()> {
     package.create_table("Directory", vec![
         msi::Column::build("Directory").primary_key().id_string(72),
@@ -361,6 +945,27 @@ fn create_directory_table(package: &mut Package, directories: &[DirectoryInfo])
             .category(msi::Category::DefaultDir).string(255),
     ])?;
     let mut rows = Vec::new();
+    match scope {
+        MsiInstallScope::User => {
+            rows.push(vec![
+                msi::Value::from("LocalAppDataFolder"),
+                msi::Value::from("TARGETDIR"),
+                msi::Value::from("."),
+            ]);
+            rows.push(vec![
+                msi::Value::from(install_root_folder(platform, scope)),
+                msi::Value::from("LocalAppDataFolder"),
+                msi::Value::from("Programs"),
+            ]);
+        }
+        MsiInstallScope::Machine | MsiInstallScope::Both => {
+            rows.push(vec![
+                msi::Value::from(install_root_folder(platform, scope)),
+                msi::Value::from("TARGETDIR"),
+                msi::Value::from("."),
+            ]);
+        }
+    }
     for directory in directories.iter() {
         rows.push(vec![
             msi::Value::Str(directory.key.clone()),
@@ -373,7 +978,11 @@ fn create_directory_table(package: &mut Package, directories: &[DirectoryInfo])
         msi::Value::Null,
         msi::Value::from("SourceDir"),
     ]).row(vec![
-        msi::Value::from("ProgramFilesFolder"),
+        msi::Value::from("ProgramMenuFolder"),
+        msi::Value::from("TARGETDIR"),
+        msi::Value::from("."),
+    ]).row(vec![
+        msi::Value::from("DesktopFolder"),
         msi::Value::from("TARGETDIR"),
         msi::Value::from("."),
     ]).rows(rows))?;
@@ -381,7 +990,9 @@ fn create_directory_table(package: &mut Package, directories: &[DirectoryInfo])
 }
 
 // Creates and populates the `Feature` database table for the package.  The
-// package will have a single main feature that installs everything.
+// package has a main feature, which installs the binary and resources and
+// which can't be deselected, followed by any optional features from the
+// settings.
 fn create_feature_table(package: &mut Package, settings: &Settings)
                         -> ::Result<()> {
     package.create_table("Feature", vec![
@@ -396,23 +1007,50 @@ fn create_feature_table(package: &mut Package, settings: &Settings)
             .foreign_key("Directory", 1).id_string(72),
         msi::Column::build("Attributes").int16(),
     ])?;
-    package.insert_rows(msi::Insert::into("Feature").row(vec![
+    let mut rows = vec![vec![
         msi::Value::from(MAIN_FEATURE_NAME),
         msi::Value::Null,
         msi::Value::from(settings.bundle_name()),
         msi::Value::Null,
         msi::Value::Int(1),
-        msi::Value::Int(3),
+        msi::Value::Int(1),
         msi::Value::from("INSTALLDIR"),
-        msi::Value::Int(0),
-    ]))?;
+        msi::Value::Int(FEATURE_ATTR_UI_DISALLOW_ABSENT),
+    ]];
+    // Features are shown in the selection tree in order of their (odd, so
+    // that any subfeatures are expanded) Display values.
+    let mut display = 3;
+    for feature in settings.msi_features() {
+        let level = feature.level.unwrap_or(1);
+        if !(0..=0x7fff).contains(&level) {
+            bail!("Invalid install level {} for MSI feature {:?}", level, feature.name);
+        }
+        rows.push(vec![
+            msi::Value::from(feature.name.as_str()),
+            msi::Value::Null,
+            msi::Value::from(feature.title.as_ref().unwrap_or(&feature.name).as_str()),
+            match feature.description {
+                Some(ref description) => msi::Value::from(description.as_str()),
+                None => msi::Value::Null,
+            },
+            msi::Value::Int(display),
+            msi::Value::Int(level),
+            msi::Value::from("INSTALLDIR"),
+            msi::Value::Int(0),
+        ]);
+        display += 2;
+    }
+    package.insert_rows(msi::Insert::into("Feature").rows(rows))?;
     Ok(())
 }
 
 // Creates and populates the `Component` database table for the package.  One
-// component is created for each subdirectory under in the install dir.
-fn create_component_table(package: &mut Package, package_guid: Uuid,
-                          directories: &[DirectoryInfo])
+// component is created for each subdirectory under in the install dir (and
+// each feature with files in it).  The component GUIDs are generated from the
+// `upgrade_code`, so that they stay the same across versions of the app.
+fn create_component_table(package: &mut Package, upgrade_code: Uuid,
+                          platform: Platform, components: &[ComponentInfo],
+                          cabinets: &[CabinetInfo])
                           -> ::Result<()> {
     package.create_table("Component", vec![
         msi::Column::build("Component").primary_key().id_string(72),
@@ -425,44 +1063,54 @@ fn create_component_table(package: &mut Package, package_guid: Uuid,
             .category(msi::Category::Condition).string(255),
         msi::Column::build("KeyPath").nullable().id_string(72),
     ])?;
+    // Each component's KeyPath is the File table key of its first file (so
+    // the main executable is the KeyPath of the install dir's component).
+    let mut key_paths = HashMap::<&str, String>::new();
+    let mut sequence: i32 = 1;
+    for cabinet in cabinets.iter() {
+        for resource in cabinet.resources.iter() {
+            key_paths.entry(resource.component_key.as_str())
+                .or_insert_with(|| file_key(sequence));
+            sequence += 1;
+        }
+    }
     let mut rows = Vec::new();
-    for directory in directories.iter() {
-        if !directory.files.is_empty() {
-            let hash_input = directory.files.join("/");
-            let uuid = Uuid::new_v5(&package_guid, hash_input.as_bytes());
-            rows.push(vec![
-                msi::Value::Str(directory.key.clone()),
-                msi::Value::from(uuid),
-                msi::Value::Str(directory.key.clone()),
-                msi::Value::Int(0),
-                msi::Value::Null,
-                msi::Value::Str(directory.files[0].clone()),
-            ]);
+    for component in components.iter() {
+        let mut hash_input = component.files.join("/");
+        if component.feature != MAIN_FEATURE_NAME {
+            hash_input = format!("{}:{}", component.feature, hash_input);
         }
+        let uuid = Uuid::new_v5(&upgrade_code, hash_input.as_bytes());
+        rows.push(vec![
+            msi::Value::Str(component.key.clone()),
+            msi::Value::from(uuid),
+            msi::Value::Str(component.directory_key.clone()),
+            msi::Value::Int(platform.component_attributes()),
+            msi::Value::Null,
+            msi::Value::Str(key_paths[component.key.as_str()].clone()),
+        ]);
     }
     package.insert_rows(msi::Insert::into("Component").rows(rows))?;
     Ok(())
 }
 
 // Creates and populates the `FeatureComponents` database table for the
-// package.  All components are added to the package's single main feature.
+// package, which adds each component to the feature its files are part of.
 fn create_feature_components_table(package: &mut Package,
-                                   directories: &[DirectoryInfo])
+                                   components: &[ComponentInfo])
                                    -> ::Result<()> {
     package.create_table("FeatureComponents", vec![
         msi::Column::build("Feature_").primary_key()
-            .foreign_key("Component", 1).id_string(38),
+            .foreign_key("Feature", 1).id_string(38),
         msi::Column::build("Component_").primary_key()
             .foreign_key("Component", 1).id_string(72),
     ])?;
     let mut rows = Vec::new();
-    for directory in directories.iter() {
-        if !directory.files.is_empty() {
-            rows.push(vec![
-                msi::Value::from(MAIN_FEATURE_NAME),
-                msi::Value::Str(directory.key.clone()),
-            ]);
-        }
+    for component in components.iter() {
+        rows.push(vec![
+            msi::Value::Str(component.feature.clone()),
+            msi::Value::Str(component.key.clone()),
+        ]);
     }
     package.insert_rows(msi::Insert::into("FeatureComponents").rows(rows))?;
     Ok(())
@@ -525,7 +1173,7 @@ fn create_file_table(package: &mut Package, cabinets: &[CabinetInfo])
     for cabinet in cabinets.iter() {
         for resource in cabinet.resources.iter() {
             rows.push(vec![
-                msi::Value::Str(format!("r{:04}", sequence)),
+                msi::Value::Str(file_key(sequence)),
                 msi::Value::Str(resource.component_key.clone()),
                 msi::Value::Str(resource.filename.clone()),
                 msi::Value::Int(resource.size as i32),
@@ -541,16 +1189,684 @@ fn create_file_table(package: &mut Package, cabinets: &[CabinetInfo])
     Ok(())
 }
 
+// Returns the database key of the File table entry with the given sequence
+// number.  The main executable is always the first file in the first cabinet,
+// so its key is `file_key(1)`.
+fn file_key(sequence: i32) -> String {
+    format!("r{:04}", sequence)
+}
+
+// Creates and populates the five sequence tables for the package, which
+// specify the standard actions that the installer runs, and in what order.
+fn create_sequence_tables(package: &mut Package) -> ::Result<()> {
+    let tables = [
+        ("InstallUISequence", INSTALL_UI_SEQUENCE),
+        ("InstallExecuteSequence", INSTALL_EXECUTE_SEQUENCE),
+        ("AdminUISequence", ADMIN_UI_SEQUENCE),
+        ("AdminExecuteSequence", ADMIN_EXECUTE_SEQUENCE),
+        ("AdvtExecuteSequence", ADVT_EXECUTE_SEQUENCE),
+    ];
+    for &(table_name, actions) in tables.iter() {
+        package.create_table(table_name, vec![
+            msi::Column::build("Action").primary_key().id_string(72),
+            msi::Column::build("Condition").nullable()
+                .category(msi::Category::Condition).string(255),
+            msi::Column::build("Sequence").nullable().range(-4, 0x7fff).int16(),
+        ])?;
+        let mut rows = Vec::new();
+        for &(action, sequence) in actions.iter() {
+            rows.push(vec![
+                msi::Value::from(action),
+                msi::Value::Null,
+                msi::Value::Int(sequence),
+            ]);
+        }
+        package.insert_rows(msi::Insert::into(table_name).rows(rows))?;
+    }
+    Ok(())
+}
+
+// Creates and populates the `Upgrade` database table for the package.
+// FindRelatedProducts uses it to detect any other versions of the app that
+// are already installed: older versions are then removed by
+// RemoveExistingProducts (a major upgrade), while newer versions prevent
+// installation (see `create_launch_condition_table`).  Another install with
+// the same version (such as a pre-release of it) counts as older, so that it
+// gets replaced rather than blocking the install.
+fn create_upgrade_table(package: &mut Package, upgrade_code: Uuid,
+                        version: &str) -> ::Result<()> {
+    package.create_table("Upgrade", vec![
+        msi::Column::build("UpgradeCode").primary_key()
+            .category(msi::Category::Guid).string(38),
+        msi::Column::build("VersionMin").primary_key().nullable().text_string(20),
+        msi::Column::build("VersionMax").primary_key().nullable().text_string(20),
+        msi::Column::build("Language").primary_key().nullable()
+            .category(msi::Category::Language).string(255),
+        msi::Column::build("Attributes").primary_key().int32(),
+        msi::Column::build("Remove").nullable().formatted_string(255),
+        msi::Column::build("ActionProperty")
+            .category(msi::Category::UpperCase).string(72),
+    ])?;
+    package.insert_rows(msi::Insert::into("Upgrade").row(vec![
+        msi::Value::from(upgrade_code),
+        msi::Value::Null,
+        msi::Value::from(version),
+        msi::Value::Null,
+        msi::Value::Int(UPGRADE_ATTR_VERSION_MAX_INCLUSIVE),
+        msi::Value::Null,
+        msi::Value::from(OLDER_VERSION_PROPERTY),
+    ]).row(vec![
+        msi::Value::from(upgrade_code),
+        msi::Value::from(version),
+        msi::Value::Null,
+        msi::Value::Null,
+        msi::Value::Int(UPGRADE_ATTR_ONLY_DETECT),
+        msi::Value::Null,
+        msi::Value::from(NEWER_VERSION_PROPERTY),
+    ]))?;
+    Ok(())
+}
+
+// Creates and populates the `LaunchCondition` database table for the
+// package, which prevents a newer version of the app from being replaced
+// with an older one.
+fn create_launch_condition_table(package: &mut Package) -> ::Result<()> {
+    package.create_table("LaunchCondition", vec![
+        msi::Column::build("Condition").primary_key()
+            .category(msi::Category::Condition).string(255),
+        msi::Column::build("Description").formatted_string(255),
+    ])?;
+    package.insert_rows(msi::Insert::into("LaunchCondition").row(vec![
+        msi::Value::Str(format!("NOT {} OR Installed", NEWER_VERSION_PROPERTY)),
+        msi::Value::from("A newer version of [ProductName] is already installed."),
+    ]))?;
+    Ok(())
+}
+
+// Creates the `Registry` database table for the package, and populates it
+// with the `msi_registry` entries from the settings.
+fn create_registry_table(package: &mut Package, settings: &Settings) -> ::Result<()> {
+    package.create_table("Registry", vec![
+        msi::Column::build("Registry").primary_key().id_string(72),
+        msi::Column::build("Root").range(-1, 3).int16(),
+        msi::Column::build("Key").category(msi::Category::RegPath).string(255),
+        msi::Column::build("Name").nullable().formatted_string(255),
+        msi::Column::build("Value").nullable().formatted_string(0),
+        msi::Column::build("Component_")
+            .foreign_key("Component", 1).id_string(72),
+    ])?;
+    let mut rows = Vec::new();
+    for (index, entry) in settings.msi_registry().iter().enumerate() {
+        let root = match REGISTRY_ROOTS.iter().find(|&&(name, _)| name == entry.root) {
+            Some(&(_, root)) => root,
+            None => bail!("Invalid registry root {:?} (expected one of HKCR, HKCU, HKLM or HKU)",
+                          entry.root),
+        };
+        rows.push(vec![
+            msi::Value::Str(format!("reg{:04}", index)),
+            msi::Value::Int(root),
+            msi::Value::Str(entry.key.clone()),
+            match entry.name {
+                Some(ref name) => msi::Value::Str(name.clone()),
+                None => msi::Value::Null,
+            },
+            msi::Value::Str(entry.value.clone()),
+            msi::Value::from(MAIN_COMPONENT_KEY),
+        ]);
+    }
+    package.insert_rows(msi::Insert::into("Registry").rows(rows))?;
+    Ok(())
+}
+
+// Creates and populates the `Environment` database table for the package,
+// with the `msi_environment` variables from the settings, plus an entry that
+// appends the install dir to PATH if `msi_add_to_path` is set.  Variables are
+// set on installation and removed on uninstallation (for PATH, only the
+// appended part is removed).  Per-machine packages set system variables, and
+// others set variables for the current user, since they may be installed
+// without admin rights.
+fn create_environment_table(package: &mut Package, settings: &Settings) -> ::Result<()> {
+    package.create_table("Environment", vec![
+        msi::Column::build("Environment").primary_key().id_string(72),
+        msi::Column::build("Name").text_string(255),
+        msi::Column::build("Value").nullable().formatted_string(255),
+        msi::Column::build("Component_")
+            .foreign_key("Component", 1).id_string(72),
+    ])?;
+    let prefix = match settings.msi_install_scope() {
+        MsiInstallScope::Machine => "=-*",
+        MsiInstallScope::User | MsiInstallScope::Both => "=-",
+    };
+    let mut variables = Vec::new();
+    if let Some(environment) = settings.msi_environment() {
+        for (name, value) in environment.iter() {
+            variables.push((name.clone(), value.clone()));
+        }
+    }
+    if settings.msi_add_to_path() {
+        variables.push(("PATH".to_string(), "[~];[INSTALLDIR]".to_string()));
+    }
+    let mut rows = Vec::new();
+    for (index, (name, value)) in variables.into_iter().enumerate() {
+        rows.push(vec![
+            msi::Value::Str(format!("env{:04}", index)),
+            msi::Value::Str(format!("{}{}", prefix, name)),
+            msi::Value::Str(value),
+            msi::Value::from(MAIN_COMPONENT_KEY),
+        ]);
+    }
+    package.insert_rows(msi::Insert::into("Environment").rows(rows))?;
+    Ok(())
+}
+
+// Creates and populates the `Shortcut` database table for the package, with
+// a Start menu shortcut and/or a desktop shortcut to the main executable,
+// depending on the settings.  Each shortcut gets its own Component, since
+// shortcuts live outside the install dir; because those folders are per-user,
+// the Component's KeyPath is a value under HKEY_CURRENT_USER rather than the
+// shortcut itself.
+fn create_shortcut_table(package: &mut Package, upgrade_code: Uuid,
+                         platform: Platform, icon_name: Option<&str>,
+                         settings: &Settings)
+                         -> ::Result<()> {
+    package.create_table("Shortcut", vec![
+        msi::Column::build("Shortcut").primary_key().id_string(72),
+        msi::Column::build("Directory_")
+            .foreign_key("Directory", 1).id_string(72),
+        msi::Column::build("Name")
+            .category(msi::Category::Filename).string(128),
+        msi::Column::build("Component_")
+            .foreign_key("Component", 1).id_string(72),
+        msi::Column::build("Target")
+            .category(msi::Category::Shortcut).string(72),
+        msi::Column::build("Arguments").nullable().formatted_string(255),
+        msi::Column::build("Description").nullable().text_string(255),
+        msi::Column::build("Hotkey").nullable().range(0, 0x7fff).int16(),
+        msi::Column::build("Icon_").nullable()
+            .foreign_key("Icon", 1).id_string(72),
+        msi::Column::build("IconIndex").nullable().range(-0x7fff, 0x7fff).int16(),
+        msi::Column::build("ShowCmd").nullable().range(0, 7).int16(),
+        msi::Column::build("WkDir").nullable().id_string(72),
+    ])?;
+    let mut shortcuts = Vec::new();
+    if settings.msi_start_menu_shortcut() {
+        shortcuts.push(("StartMenuShortcut", "ProgramMenuFolder"));
+    }
+    if settings.msi_desktop_shortcut() {
+        shortcuts.push(("DesktopShortcut", "DesktopFolder"));
+    }
+    let registry_key = format!("Software\\{}", settings.bundle_name());
+    let mut component_rows = Vec::new();
+    let mut feature_component_rows = Vec::new();
+    let mut registry_rows = Vec::new();
+    let mut shortcut_rows = Vec::new();
+    for &(key, directory) in shortcuts.iter() {
+        let uuid = Uuid::new_v5(&upgrade_code, key.as_bytes());
+        component_rows.push(vec![
+            msi::Value::from(key),
+            msi::Value::from(uuid),
+            msi::Value::from(directory),
+            msi::Value::Int(COMPONENT_ATTR_REGISTRY_KEY_PATH | platform.component_attributes()),
+            msi::Value::Null,
+            msi::Value::from(key),
+        ]);
+        feature_component_rows.push(vec![
+            msi::Value::from(MAIN_FEATURE_NAME),
+            msi::Value::from(key),
+        ]);
+        registry_rows.push(vec![
+            msi::Value::from(key),
+            msi::Value::Int(REGISTRY_ROOT_CURRENT_USER),
+            msi::Value::Str(registry_key.clone()),
+            msi::Value::from(key),
+            msi::Value::from("#1"),
+            msi::Value::from(key),
+        ]);
+        shortcut_rows.push(vec![
+            msi::Value::from(key),
+            msi::Value::from(directory),
+            msi::Value::from(settings.bundle_name()),
+            msi::Value::from(key),
+            msi::Value::Str(format!("[#{}]", file_key(1))),
+            msi::Value::Null,
+            msi::Value::from(settings.short_description()),
+            msi::Value::Null,
+            icon_value(icon_name),
+            icon_index_value(icon_name),
+            msi::Value::Null,
+            msi::Value::from("INSTALLDIR"),
+        ]);
+    }
+    package.insert_rows(msi::Insert::into("Component").rows(component_rows))?;
+    package.insert_rows(msi::Insert::into("FeatureComponents").rows(feature_component_rows))?;
+    package.insert_rows(msi::Insert::into("Registry").rows(registry_rows))?;
+    package.insert_rows(msi::Insert::into("Shortcut").rows(shortcut_rows))?;
+    Ok(())
+}
+
+// Creates and populates the `ProgId`, `Extension`, `Verb` and `MIME`
+// database tables for the package, which register the app to open the file
+// types from the `file_associations` setting.  Also adds entries to the
+// `Registry` table to register the app as the handler for the URL schemes
+// from the `url_schemes` setting.  (For per-user installs, the installer
+// writes these HKEY_CLASSES_ROOT entries under HKEY_CURRENT_USER instead.)
+fn create_association_tables(package: &mut Package, icon_name: Option<&str>,
+                             settings: &Settings) -> ::Result<()> {
+    package.create_table("ProgId", vec![
+        msi::Column::build("ProgId").primary_key().text_string(255),
+        msi::Column::build("ProgId_Parent").nullable()
+            .foreign_key("ProgId", 1).text_string(255),
+        msi::Column::build("Class_").nullable()
+            .category(msi::Category::Guid).string(38),
+        msi::Column::build("Description").nullable().text_string(255),
+        msi::Column::build("Icon_").nullable()
+            .foreign_key("Icon", 1).id_string(72),
+        msi::Column::build("IconIndex").nullable().range(-0x7fff, 0x7fff).int16(),
+    ])?;
+    package.create_table("Extension", vec![
+        msi::Column::build("Extension").primary_key().text_string(255),
+        msi::Column::build("Component_").primary_key()
+            .foreign_key("Component", 1).id_string(72),
+        msi::Column::build("ProgId_").nullable()
+            .foreign_key("ProgId", 1).text_string(255),
+        msi::Column::build("MIME_").nullable()
+            .foreign_key("MIME", 1).text_string(64),
+        msi::Column::build("Feature_")
+            .foreign_key("Feature", 1).id_string(38),
+    ])?;
+    package.create_table("Verb", vec![
+        msi::Column::build("Extension_").primary_key()
+            .foreign_key("Extension", 1).text_string(255),
+        msi::Column::build("Verb").primary_key().text_string(32),
+        msi::Column::build("Sequence").nullable().range(0, 0x7fff).int16(),
+        msi::Column::build("Command").nullable().formatted_string(255),
+        msi::Column::build("Argument").nullable().formatted_string(255),
+    ])?;
+    package.create_table("MIME", vec![
+        msi::Column::build("ContentType").primary_key().text_string(64),
+        msi::Column::build("Extension_")
+            .foreign_key("Extension", 1).text_string(255),
+        msi::Column::build("CLSID").nullable()
+            .category(msi::Category::Guid).string(38),
+    ])?;
+    let mut prog_id_rows = Vec::new();
+    let mut extension_rows = Vec::new();
+    let mut verb_rows = Vec::new();
+    let mut mime_rows = Vec::new();
+    for association in settings.file_associations() {
+        let first_extension = match association.extensions.first() {
+            Some(extension) => extension,
+            None => bail!("File association has no extensions"),
+        };
+        let prog_id = format!("{}.{}", settings.bundle_identifier(), first_extension);
+        prog_id_rows.push(vec![
+            msi::Value::Str(prog_id.clone()),
+            msi::Value::Null,
+            msi::Value::Null,
+            msi::Value::Str(association.display_name()),
+            icon_value(icon_name),
+            icon_index_value(icon_name),
+        ]);
+        let mime_type = match association.mime_type {
+            Some(ref mime_type) => msi::Value::Str(mime_type.clone()),
+            None => msi::Value::Null,
+        };
+        for extension in association.extensions.iter() {
+            extension_rows.push(vec![
+                msi::Value::Str(extension.clone()),
+                msi::Value::from(MAIN_COMPONENT_KEY),
+                msi::Value::Str(prog_id.clone()),
+                mime_type.clone(),
+                msi::Value::from(MAIN_FEATURE_NAME),
+            ]);
+            // The verb's command line is the Component's KeyPath (i.e. the
+            // main executable), followed by the argument.
+            verb_rows.push(vec![
+                msi::Value::Str(extension.clone()),
+                msi::Value::from("open"),
+                msi::Value::Int(1),
+                msi::Value::from("&Open"),
+                msi::Value::from("\"%1\""),
+            ]);
+        }
+        if let Some(ref mime_type) = association.mime_type {
+            mime_rows.push(vec![
+                msi::Value::Str(mime_type.clone()),
+                msi::Value::Str(first_extension.clone()),
+                msi::Value::Null,
+            ]);
+        }
+    }
+    package.insert_rows(msi::Insert::into("ProgId").rows(prog_id_rows))?;
+    package.insert_rows(msi::Insert::into("Extension").rows(extension_rows))?;
+    package.insert_rows(msi::Insert::into("Verb").rows(verb_rows))?;
+    package.insert_rows(msi::Insert::into("MIME").rows(mime_rows))?;
+
+    let executable = format!("[#{}]", file_key(1));
+    let mut registry_rows = Vec::new();
+    for (index, scheme) in settings.url_schemes().iter().enumerate() {
+        let values = [
+            (scheme.clone(), None, Some(format!("URL:{} Protocol", settings.bundle_name()))),
+            (scheme.clone(), Some("URL Protocol"), None),
+            (format!("{}\\DefaultIcon", scheme), None, Some(format!("\"{}\",0", executable))),
+            (format!("{}\\shell\\open\\command", scheme), None,
+             Some(format!("\"{}\" \"%1\"", executable))),
+        ];
+        for (value_index, &(ref key, name, ref value)) in values.iter().enumerate() {
+            registry_rows.push(vec![
+                msi::Value::Str(format!("url{:04}_{}", index, value_index)),
+                msi::Value::Int(REGISTRY_ROOT_CLASSES),
+                msi::Value::Str(key.clone()),
+                match name {
+                    Some(name) => msi::Value::from(name),
+                    None => msi::Value::Null,
+                },
+                match *value {
+                    Some(ref value) => msi::Value::Str(value.clone()),
+                    None => msi::Value::Null,
+                },
+                msi::Value::from(MAIN_COMPONENT_KEY),
+            ]);
+        }
+    }
+    package.insert_rows(msi::Insert::into("Registry").rows(registry_rows))?;
+    Ok(())
+}
+
+// Reads the license agreement at the given path, converting it to RTF unless
+// it is already an RTF file.
+fn read_license_rtf(path: &Path) -> ::Result<String> {
+    let text = fs::read_to_string(path).chain_err(|| {
+        format!("Failed to read license file {:?}", path)
+    })?;
+    if path.extension() == Some(OsStr::new("rtf")) {
+        Ok(text)
+    } else {
+        Ok(msi_ui::text_to_rtf(&text))
+    }
+}
+
+// Returns the values of an `Icon_` column and its `IconIndex` column for the
+// app icon, or nulls if the app has no icon.
+fn icon_value(icon_name: Option<&str>) -> msi::Value {
+    icon_name.map_or(msi::Value::Null, msi::Value::from)
+}
+
+fn icon_index_value(icon_name: Option<&str>) -> msi::Value {
+    if icon_name.is_some() { msi::Value::Int(0) } else { msi::Value::Null }
+}
+
+// Writes the app icon in ICO format, returning false (having written nothing)
+// if there are no icon files or no images big enough to use.
 fn create_app_icon<W: Write>(writer: &mut W, settings: &Settings)
-                             -> ::Result<()> {
+                             -> ::Result<bool> {
     // Prefer ICO files.
     for icon_path in settings.icon_files() {
         let icon_path = icon_path?;
         if icon_path.extension() == Some(OsStr::new("ico")) {
             io::copy(&mut fs::File::open(icon_path)?, writer)?;
-            return Ok(());
+            return Ok(true);
         }
     }
-    // TODO: Convert from other formats.
-    Ok(())
+    // Otherwise, read the available images, and build an ICO file out of them.
+    let mut icons = Vec::new();
+    for icon_path in settings.icon_files() {
+        let icon_path = icon_path?;
+        if icon_path.extension() == Some(OsStr::new("icns")) {
+            let icon_family = icns::IconFamily::read(fs::File::open(&icon_path)?)?;
+            for icon_type in icon_family.available_icons() {
+                let mut png = Vec::new();
+                icon_family.get_icon_with_type(icon_type)?.write_png(&mut png)?;
+                icons.push(image::load_from_memory(&png)?);
+            }
+        } else {
+            icons.push(image::open(&icon_path)?);
+        }
+    }
+    let mut images = Vec::new();
+    for &size in ICON_SIZES.iter() {
+        // Scale down from the smallest image that is at least this big, if
+        // there is one.
+        let icon = icons.iter()
+            .filter(|icon| min(icon.width(), icon.height()) >= size)
+            .min_by_key(|icon| min(icon.width(), icon.height()));
+        if let Some(icon) = icon {
+            if icon.dimensions() == (size, size) {
+                images.push(icon.clone());
+            } else {
+                images.push(common::resize_icon(icon, size));
+            }
+        }
+    }
+    if images.is_empty() {
+        return Ok(false);
+    }
+    ico::write_ico(&images, writer)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PID_WORDCOUNT, Platform, SUMMARY_INFO_STREAM, WORD_COUNT_COMPRESSED,
+                WORD_COUNT_NO_ELEVATION, add_install_scope_properties, create_directory_table,
+                UPGRADE_ATTR_ONLY_DETECT, UPGRADE_ATTR_VERSION_MAX_INCLUSIVE,
+                create_sequence_tables, create_upgrade_table, generate_product_code,
+                new_empty_package, set_summary_word_count};
+    use bundle::version::Version;
+    use bundle::settings::MsiInstallScope;
+    use cfb;
+    use msi;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Read;
+    use std::path::Path;
+    use tempfile;
+    use uuid::Uuid;
+
+    // Reads the word count property from the summary information of the
+    // package at the given path, if it has one.
+    fn read_word_count(msi_path: &Path) -> Option<i32> {
+        let mut data = Vec::new();
+        cfb::open(msi_path).unwrap().open_stream(SUMMARY_INFO_STREAM).unwrap()
+            .read_to_end(&mut data).unwrap();
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2],
+                                data[offset + 3]])
+        };
+        let section = u32_at(44) as usize;
+        (0..u32_at(section + 4) as usize).map(|index| section + 8 + 8 * index)
+            .find(|&entry| u32_at(entry) == PID_WORDCOUNT)
+            .map(|entry| {
+                let offset = section + u32_at(entry + 4) as usize;
+                assert_eq!(u32_at(offset), 3, "word count should be a VT_I4");
+                u32_at(offset + 4) as i32
+            })
+    }
+
+    #[test]
+    fn product_codes() {
+        let upgrade_code = Uuid::from_bytes([7; 16]);
+        let product_code = |version: &str| {
+            let msi_version = Version::parse(version).unwrap().msi_version().unwrap();
+            generate_product_code(upgrade_code, &msi_version)
+        };
+        assert_eq!(product_code("1.2.3-beta.1"), product_code("1.2.3"));
+        assert_eq!(product_code("1.2.3+b1"), product_code("1.2.3+b2"));
+        assert_ne!(product_code("1.2.3"), product_code("1.2.4"));
+        assert_ne!(generate_product_code(Uuid::from_bytes([8; 16]), "1.2.3"),
+                   product_code("1.2.3"));
+    }
+
+    // Returns the ActionProperty of each row of the package's Upgrade table
+    // that FindRelatedProducts would match against an installed product with
+    // the given version, along with whether that row only detects it.
+    fn related_products(msi_path: &Path, installed: &str) -> Vec<(String, bool)> {
+        let parse = |version: &str| -> Vec<u32> {
+            version.split('.').map(|part| part.parse().unwrap()).collect()
+        };
+        let installed = parse(installed);
+        let mut package = msi::Package::open(fs::File::open(msi_path).unwrap()).unwrap();
+        let rows = package.select_rows(msi::Select::table("Upgrade")).unwrap();
+        rows.filter(|row| {
+            let attributes = row["Attributes"].as_int().unwrap();
+            let above_min = match row["VersionMin"].as_str() {
+                Some(min) => installed > parse(min),
+                None => true,
+            };
+            let below_max = match row["VersionMax"].as_str() {
+                Some(max) if attributes & UPGRADE_ATTR_VERSION_MAX_INCLUSIVE != 0 => {
+                    installed <= parse(max)
+                }
+                Some(max) => installed < parse(max),
+                None => true,
+            };
+            above_min && below_max
+        }).map(|row| {
+            (row["ActionProperty"].as_str().unwrap().to_string(),
+             row["Attributes"].as_int().unwrap() & UPGRADE_ATTR_ONLY_DETECT != 0)
+        }).collect()
+    }
+
+    #[test]
+    fn upgrade_table() {
+        let tmp = tempfile::tempdir().unwrap();
+        let msi_path = tmp.path().join("test.msi");
+        {
+            // The package for 1.2.3, which 1.2.3-beta.1 also has as its MSI
+            // version.
+            let version = Version::parse("1.2.3").unwrap().msi_version().unwrap();
+            let mut package = new_empty_package(&msi_path).unwrap();
+            create_upgrade_table(&mut package, Uuid::from_bytes([7; 16]), &version).unwrap();
+            package.flush().unwrap();
+        }
+        let older = vec![("OLDERVERSIONDETECTED".to_string(), false)];
+        let newer = vec![("NEWERVERSIONDETECTED".to_string(), true)];
+        // An installed pre-release of the same version is upgraded, just like
+        // an older version, rather than blocking the install.
+        let pre_release = Version::parse("1.2.3-beta.1").unwrap().msi_version().unwrap();
+        assert_eq!(related_products(&msi_path, &pre_release), older);
+        assert_eq!(related_products(&msi_path, "1.2.2"), older);
+        assert_eq!(related_products(&msi_path, "1.2.4"), newer);
+    }
+
+    #[test]
+    fn summary_word_count() {
+        let tmp = tempfile::tempdir().unwrap();
+        let msi_path = tmp.path().join("test.msi");
+        {
+            let mut package = new_empty_package(&msi_path).unwrap();
+            package.summary_info_mut().set_subject("Example".to_string());
+            package.summary_info_mut().set_author("Jane Doe".to_string());
+            package.flush().unwrap();
+        }
+        assert_eq!(read_word_count(&msi_path), None);
+        let per_user = WORD_COUNT_COMPRESSED | WORD_COUNT_NO_ELEVATION;
+        set_summary_word_count(&msi_path, per_user).unwrap();
+        assert_eq!(read_word_count(&msi_path), Some(0xa));
+        // The other summary properties are still readable after the word
+        // count is added.
+        {
+            let package = msi::Package::open(fs::File::open(&msi_path).unwrap()).unwrap();
+            assert_eq!(package.summary_info().subject(), Some("Example"));
+            assert_eq!(package.summary_info().author(), Some("Jane Doe"));
+        }
+        // Setting it again replaces the existing value.
+        set_summary_word_count(&msi_path, WORD_COUNT_COMPRESSED).unwrap();
+        assert_eq!(read_word_count(&msi_path), Some(0x2));
+        let package = msi::Package::open(fs::File::open(&msi_path).unwrap()).unwrap();
+        assert_eq!(package.summary_info().subject(), Some("Example"));
+    }
+
+    // Builds a package with just the install scope properties and the
+    // Directory table for the given scope, then reopens it and returns its
+    // properties and a map from each directory to its parent.
+    fn scope_tables(platform: Platform, scope: MsiInstallScope)
+                    -> (HashMap<String, String>, HashMap<String, Option<String>>) {
+        let tmp = tempfile::tempdir().unwrap();
+        let msi_path = tmp.path().join("test.msi");
+        {
+            let mut package = new_empty_package(&msi_path).unwrap();
+            package.create_table("Property", vec![
+                msi::Column::build("Property").primary_key().id_string(72),
+                msi::Column::build("Value").text_string(0),
+            ]).unwrap();
+            add_install_scope_properties(&mut package, scope).unwrap();
+            create_directory_table(&mut package, platform, scope, &[]).unwrap();
+            package.flush().unwrap();
+        }
+        let mut package = msi::Package::open(fs::File::open(&msi_path).unwrap()).unwrap();
+        let properties = package.select_rows(msi::Select::table("Property")).unwrap()
+            .map(|row| {
+                (row["Property"].as_str().unwrap().to_string(),
+                 row["Value"].as_str().unwrap().to_string())
+            }).collect();
+        let directories = package.select_rows(msi::Select::table("Directory")).unwrap()
+            .map(|row| {
+                (row["Directory"].as_str().unwrap().to_string(),
+                 row["Directory_Parent"].as_str().map(str::to_string))
+            }).collect();
+        (properties, directories)
+    }
+
+    #[test]
+    fn install_scopes() {
+        let parent = |directories: &HashMap<String, Option<String>>, key: &str| {
+            directories.get(key).cloned()
+                .unwrap_or_else(|| panic!("missing directory {}", key))
+        };
+
+        let (properties, directories) = scope_tables(Platform::X64, MsiInstallScope::User);
+        assert_eq!(properties.get("ALLUSERS").map(String::as_str), Some("2"));
+        assert_eq!(properties.get("MSIINSTALLPERUSER").map(String::as_str), Some("1"));
+        assert_eq!(parent(&directories, "LocalAppDataFolder"), Some("TARGETDIR".to_string()));
+        assert_eq!(parent(&directories, "LocalProgramsFolder"),
+                   Some("LocalAppDataFolder".to_string()));
+        assert!(!directories.contains_key("ProgramFiles64Folder"));
+
+        let (properties, directories) = scope_tables(Platform::X64, MsiInstallScope::Machine);
+        assert_eq!(properties.get("ALLUSERS").map(String::as_str), Some("1"));
+        assert_eq!(properties.get("MSIINSTALLPERUSER"), None);
+        assert_eq!(parent(&directories, "ProgramFiles64Folder"), Some("TARGETDIR".to_string()));
+        assert!(!directories.contains_key("LocalProgramsFolder"));
+
+        let (properties, directories) = scope_tables(Platform::X86, MsiInstallScope::Both);
+        assert_eq!(properties.get("ALLUSERS").map(String::as_str), Some("2"));
+        assert_eq!(properties.get("MSIINSTALLPERUSER"), None);
+        assert_eq!(parent(&directories, "ProgramFilesFolder"), Some("TARGETDIR".to_string()));
+        assert!(!directories.contains_key("LocalProgramsFolder"));
+    }
+
+    #[test]
+    fn sequence_tables() {
+        let tmp = tempfile::tempdir().unwrap();
+        let msi_path = tmp.path().join("test.msi");
+        {
+            let mut package = new_empty_package(&msi_path).unwrap();
+            create_sequence_tables(&mut package).unwrap();
+            package.flush().unwrap();
+        }
+        let mut package = msi::Package::open(fs::File::open(&msi_path).unwrap()).unwrap();
+        for table in &["InstallUISequence", "InstallExecuteSequence", "AdminUISequence",
+                       "AdminExecuteSequence", "AdvtExecuteSequence"] {
+            assert!(package.has_table(table), "missing {}", table);
+        }
+        let rows = package.select_rows(msi::Select::table("InstallExecuteSequence")).unwrap();
+        let mut actions: Vec<(String, i32)> = rows.map(|row| {
+            (row["Action"].as_str().unwrap().to_string(), row["Sequence"].as_int().unwrap())
+        }).collect();
+        actions.sort_by_key(|&(_, sequence)| sequence);
+        let names: Vec<&str> = actions.iter().map(|(name, _)| name.as_str()).collect();
+        let position = |action: &str| {
+            names.iter().position(|&name| name == action)
+                .unwrap_or_else(|| panic!("missing action {}", action))
+        };
+        assert!(position("CostInitialize") < position("FileCost"));
+        assert!(position("FileCost") < position("CostFinalize"));
+        assert!(position("CostFinalize") < position("InstallValidate"));
+        assert!(position("InstallValidate") < position("InstallInitialize"));
+        assert!(position("InstallInitialize") < position("InstallFiles"));
+        assert!(position("InstallFiles") < position("RegisterProduct"));
+        assert!(position("RegisterProduct") < position("PublishProduct"));
+        assert_eq!(names.last(), Some(&"InstallFinalize"));
+    }
 }
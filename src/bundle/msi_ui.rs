@@ -0,0 +1,525 @@
+// The built-in installer UI for MSI packages, modelled on WiX's
+// WixUI_InstallDir dialog set.  The wizard runs:
+//
+// WelcomeDlg -> LicenseDlg -> FeaturesDlg -> InstallDirDlg -> ProgressDlg -> ExitDialog
+//
+// where LicenseDlg is only included if there is a license agreement to show
+// (and the user must accept it before continuing), and FeaturesDlg, which
+// lets the user choose which features to install, only if the package has
+// optional features.  There are also the
+// UserExit and FatalError dialogs (shown instead of ExitDialog if the user
+// cancels, or if installation fails), CancelDlg (which asks the user to
+// confirm cancelling), and ErrorDlg (which the installer uses to show error
+// messages).  The UI is only shown for new installs; repairs and removals run
+// with just the progress dialog.
+//
+// For more information about the tables involved, see
+// https://docs.microsoft.com/en-us/windows/win32/msi/controls
+
+use msi;
+use std::io::{Read, Seek, Write};
+
+// Dialog table attributes:
+const DIALOG_ATTR_VISIBLE: i32 = 0x1;
+const DIALOG_ATTR_MODAL: i32 = 0x2;
+const DIALOG_ATTR_MINIMIZE: i32 = 0x4;
+const DIALOG_ATTR_ERROR: i32 = 0x10000;
+
+// Control table attributes:
+const CONTROL_ATTR_VISIBLE: i32 = 0x1;
+const CONTROL_ATTR_ENABLED: i32 = 0x2;
+const CONTROL_ATTR_SUNKEN: i32 = 0x4;
+const CONTROL_ATTR_TRANSPARENT: i32 = 0x10000;
+const CONTROL_ATTR_PROGRESS_95: i32 = 0x10000;
+const CONTROL_ATTR_NO_PREFIX: i32 = 0x20000;
+
+const WIZARD_ATTRS: i32 = DIALOG_ATTR_VISIBLE | DIALOG_ATTR_MODAL | DIALOG_ATTR_MINIMIZE;
+const ACTIVE_ATTRS: i32 = CONTROL_ATTR_VISIBLE | CONTROL_ATTR_ENABLED;
+const TEXT_ATTRS: i32 = ACTIVE_ATTRS | CONTROL_ATTR_TRANSPARENT | CONTROL_ATTR_NO_PREFIX;
+
+// The size of the wizard dialogs, in installer units:
+const WIZARD_WIDTH: i32 = 370;
+const WIZARD_HEIGHT: i32 = 270;
+
+// The property that the license agreement checkbox sets:
+const LICENSE_ACCEPTED_PROPERTY: &str = "LicenseAccepted";
+// The property that the feature selection tree stores the selected feature's
+// directory in (features don't have their own directories, so it is unused):
+const BROWSE_PROPERTY: &str = "_BrowseProperty";
+
+// Text styles (name, font, size, style bits) that control text can refer to
+// with a `{\Name}` prefix:
+const TEXT_STYLES: &[(&str, &str, i32, i32)] = &[
+    ("DlgFont8", "Tahoma", 8, 0),
+    ("TitleFont", "Tahoma", 9, 1),
+    ("BigFont", "Tahoma", 12, 1),
+];
+
+// The buttons on ErrorDlg, and the events they end the dialog with.  The
+// installer shows whichever buttons are appropriate for each error.
+const ERROR_BUTTONS: &[(&str, &str, &str)] = &[
+    ("A", "&Abort", "ErrorAbort"),
+    ("C", "&Cancel", "ErrorCancel"),
+    ("I", "&Ignore", "ErrorIgnore"),
+    ("N", "&No", "ErrorNo"),
+    ("O", "&OK", "ErrorOk"),
+    ("R", "&Retry", "ErrorRetry"),
+    ("Y", "&Yes", "ErrorYes"),
+];
+
+/// Creates and populates the database tables for the installer UI, and adds
+/// its dialogs to the `InstallUISequence` table (which must already exist).
+/// If `license_rtf` is given, the wizard includes a license agreement dialog
+/// showing it, and if `show_features` is true, it includes a dialog for
+/// choosing which features to install.
+pub fn create_ui_tables<F>(package: &mut msi::Package<F>, license_rtf: Option<&str>,
+                           show_features: bool)
+                           -> ::Result<()>
+    where F: Read + Write + Seek
+{
+    let mut ui = UiTables::default();
+    let mut pages = vec!["WelcomeDlg"];
+    if license_rtf.is_some() {
+        pages.push("LicenseDlg");
+    }
+    if show_features {
+        pages.push("FeaturesDlg");
+    }
+    pages.push("InstallDirDlg");
+    let page_index = |page: &str| pages.iter().position(|&p| p == page).unwrap();
+    let next_page = |page: &str| pages[page_index(page) + 1];
+    let prev_page = |page: &str| pages[page_index(page) - 1];
+
+    ui.wizard_dialog("WelcomeDlg", "Next", "Next");
+    ui.big_title("WelcomeDlg", "Welcome to the [ProductName] Setup Wizard");
+    ui.text("WelcomeDlg", "Description", (20, 90, 330, 50),
+            "The Setup Wizard will install [ProductName] on your computer.  \
+             Click Next to continue or Cancel to exit the Setup Wizard.");
+    ui.wizard_buttons("WelcomeDlg", None, "&Next", true);
+    ui.event("WelcomeDlg", "Next", "NewDialog", next_page("WelcomeDlg"), "1", 1);
+
+    if let Some(rtf) = license_rtf {
+        ui.wizard_dialog("LicenseDlg", "LicenseAcceptedCheckBox", "Next");
+        ui.banner("LicenseDlg", "End-User License Agreement",
+                  "Please read the following license agreement carefully.");
+        ui.control("LicenseDlg", "LicenseText", "ScrollableText", (20, 55, 330, 140),
+                   ACTIVE_ATTRS | CONTROL_ATTR_SUNKEN);
+        ui.control_text("LicenseDlg", "LicenseText", rtf);
+        ui.control_next("LicenseDlg", "LicenseText", "LicenseAcceptedCheckBox");
+        ui.control("LicenseDlg", "LicenseAcceptedCheckBox", "CheckBox", (20, 202, 330, 18),
+                   ACTIVE_ATTRS);
+        ui.control_property("LicenseDlg", "LicenseAcceptedCheckBox", LICENSE_ACCEPTED_PROPERTY);
+        ui.control_text("LicenseDlg", "LicenseAcceptedCheckBox",
+                        "I &accept the terms in the License Agreement");
+        ui.control_next("LicenseDlg", "LicenseAcceptedCheckBox", "Back");
+        ui.wizard_buttons("LicenseDlg", Some(prev_page("LicenseDlg")), "&Next", true);
+        ui.control_next("LicenseDlg", "Cancel", "LicenseText");
+        let accepted = format!("{} = \"1\"", LICENSE_ACCEPTED_PROPERTY);
+        let not_accepted = format!("{} <> \"1\"", LICENSE_ACCEPTED_PROPERTY);
+        ui.event("LicenseDlg", "Next", "NewDialog", next_page("LicenseDlg"), &accepted, 1);
+        ui.condition("LicenseDlg", "Next", "Disable", &not_accepted);
+        ui.condition("LicenseDlg", "Next", "Enable", &accepted);
+    }
+
+    if show_features {
+        ui.wizard_dialog("FeaturesDlg", "Tree", "Next");
+        ui.banner("FeaturesDlg", "Custom Setup",
+                  "Select the way you want features to be installed.");
+        ui.control("FeaturesDlg", "Tree", "SelectionTree", (20, 55, 175, 170),
+                   ACTIVE_ATTRS | CONTROL_ATTR_SUNKEN);
+        ui.control_property("FeaturesDlg", "Tree", BROWSE_PROPERTY);
+        ui.control_next("FeaturesDlg", "Tree", "Back");
+        // These show the description and size of the selected feature.
+        ui.text("FeaturesDlg", "ItemDescription", (205, 55, 145, 80), "");
+        ui.text("FeaturesDlg", "ItemSize", (205, 145, 145, 80), "");
+        ui.mapping("FeaturesDlg", "ItemDescription", "SelectionDescription", "Text");
+        ui.mapping("FeaturesDlg", "ItemSize", "SelectionSize", "Text");
+        ui.wizard_buttons("FeaturesDlg", Some(prev_page("FeaturesDlg")), "&Next", true);
+        ui.control_next("FeaturesDlg", "Cancel", "Tree");
+        ui.event("FeaturesDlg", "Next", "NewDialog", next_page("FeaturesDlg"), "1", 1);
+    }
+
+    ui.wizard_dialog("InstallDirDlg", "Folder", "Next");
+    ui.banner("InstallDirDlg", "Destination Folder",
+              "Click Install to install [ProductName] to this folder, or change it first.");
+    ui.text("InstallDirDlg", "FolderLabel", (20, 60, 330, 15), "Install [ProductName] to:");
+    ui.control("InstallDirDlg", "Folder", "PathEdit", (20, 78, 330, 18), ACTIVE_ATTRS);
+    ui.control_property("InstallDirDlg", "Folder", "INSTALLDIR");
+    ui.control_next("InstallDirDlg", "Folder", "Back");
+    ui.wizard_buttons("InstallDirDlg", Some(prev_page("InstallDirDlg")), "&Install", true);
+    ui.control_next("InstallDirDlg", "Cancel", "Folder");
+    ui.event("InstallDirDlg", "Next", "SetTargetPath", "INSTALLDIR", "1", 1);
+    ui.event("InstallDirDlg", "Next", "EndDialog", "Return", "1", 2);
+
+    // The progress dialog is modeless, so that it stays up while the
+    // installer runs.
+    ui.dialog("ProgressDlg", (WIZARD_WIDTH, WIZARD_HEIGHT),
+              DIALOG_ATTR_VISIBLE | DIALOG_ATTR_MINIMIZE, "Cancel", "Cancel", "Cancel");
+    ui.banner("ProgressDlg", "Installing [ProductName]",
+              "Please wait while the Setup Wizard installs [ProductName].");
+    ui.text("ProgressDlg", "ActionText", (20, 90, 330, 15), "");
+    ui.control("ProgressDlg", "ProgressBar", "ProgressBar", (20, 110, 330, 12),
+               CONTROL_ATTR_VISIBLE | CONTROL_ATTR_PROGRESS_95);
+    ui.control_text("ProgressDlg", "ProgressBar", "Progress done");
+    ui.wizard_buttons("ProgressDlg", None, "&Next", false);
+    ui.mapping("ProgressDlg", "ActionText", "ActionText", "Text");
+    ui.mapping("ProgressDlg", "ProgressBar", "SetProgress", "Progress");
+
+    let final_dialogs = [
+        ("ExitDialog", "Completed the [ProductName] Setup Wizard",
+         "Click the Finish button to exit the Setup Wizard.", "Return"),
+        ("UserExit", "[ProductName] Setup was interrupted",
+         "Your system has not been modified.  To install this program at a later time, \
+          run the Setup Wizard again.", "Exit"),
+        ("FatalError", "[ProductName] Setup ended prematurely",
+         "An error prevented the Setup Wizard from completing.  Your system has not been \
+          modified.  To install this program at a later time, run the Setup Wizard again.",
+         "Exit"),
+    ];
+    for &(dialog, title, description, end) in final_dialogs.iter() {
+        ui.dialog(dialog, (WIZARD_WIDTH, WIZARD_HEIGHT), WIZARD_ATTRS,
+                  "Finish", "Finish", "Finish");
+        ui.big_title(dialog, title);
+        ui.text(dialog, "Description", (20, 90, 330, 50), description);
+        ui.control(dialog, "BottomLine", "Line", (0, 234, WIZARD_WIDTH, 0), CONTROL_ATTR_VISIBLE);
+        ui.control(dialog, "Finish", "PushButton", (236, 243, 56, 17), ACTIVE_ATTRS);
+        ui.control_text(dialog, "Finish", "&Finish");
+        ui.control_next(dialog, "Finish", "Finish");
+        ui.event(dialog, "Finish", "EndDialog", end, "1", 1);
+    }
+
+    ui.dialog("CancelDlg", (260, 85), DIALOG_ATTR_VISIBLE | DIALOG_ATTR_MODAL, "No", "No", "No");
+    ui.text("CancelDlg", "Text", (20, 15, 220, 30),
+            "Are you sure you want to cancel the installation of [ProductName]?");
+    ui.control("CancelDlg", "Yes", "PushButton", (72, 57, 56, 17), ACTIVE_ATTRS);
+    ui.control_text("CancelDlg", "Yes", "&Yes");
+    ui.control_next("CancelDlg", "Yes", "No");
+    ui.control("CancelDlg", "No", "PushButton", (132, 57, 56, 17), ACTIVE_ATTRS);
+    ui.control_text("CancelDlg", "No", "&No");
+    ui.control_next("CancelDlg", "No", "Yes");
+    ui.event("CancelDlg", "Yes", "EndDialog", "Exit", "1", 1);
+    ui.event("CancelDlg", "No", "EndDialog", "Return", "1", 1);
+
+    ui.dialog("ErrorDlg", (270, 105),
+              DIALOG_ATTR_VISIBLE | DIALOG_ATTR_MODAL | DIALOG_ATTR_ERROR, "ErrorText", "O", "C");
+    ui.control("ErrorDlg", "ErrorText", "Text", (20, 15, 230, 55), TEXT_ATTRS);
+    ui.control_next("ErrorDlg", "ErrorText", ERROR_BUTTONS[0].0);
+    for (index, &(button, text, event)) in ERROR_BUTTONS.iter().enumerate() {
+        let next = ERROR_BUTTONS.get(index + 1).map(|&(next, _, _)| next).unwrap_or("ErrorText");
+        ui.control("ErrorDlg", button, "PushButton", (107, 80, 56, 17), ACTIVE_ATTRS);
+        ui.control_text("ErrorDlg", button, text);
+        ui.control_next("ErrorDlg", button, next);
+        ui.event("ErrorDlg", button, "EndDialog", event, "1", 1);
+    }
+
+    ui.write(package)
+}
+
+/// Converts plain text into an RTF document, for display in the license
+/// agreement dialog.
+pub fn text_to_rtf(text: &str) -> String {
+    let mut rtf = String::from("{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fnil Tahoma;}}\\f0\\fs16\n");
+    for ch in text.chars() {
+        match ch {
+            '\\' | '{' | '}' => {
+                rtf.push('\\');
+                rtf.push(ch);
+            }
+            '\n' => rtf.push_str("\\par\n"),
+            '\r' => {}
+            ' '..='~' => rtf.push(ch),
+            _ => {
+                let mut units = [0u16; 2];
+                for &unit in ch.encode_utf16(&mut units).iter() {
+                    rtf.push_str(&format!("\\u{}?", unit as i16));
+                }
+            }
+        }
+    }
+    rtf.push_str("}\n");
+    rtf
+}
+
+// Rows for the UI database tables, built up dialog by dialog.
+#[derive(Default)]
+struct UiTables {
+    dialogs: Vec<Vec<msi::Value>>,
+    controls: Vec<Vec<msi::Value>>,
+    events: Vec<Vec<msi::Value>>,
+    conditions: Vec<Vec<msi::Value>>,
+    mappings: Vec<Vec<msi::Value>>,
+}
+
+impl UiTables {
+    fn dialog(&mut self, dialog: &str, (width, height): (i32, i32), attributes: i32,
+              first: &str, default: &str, cancel: &str) {
+        self.dialogs.push(vec![
+            msi::Value::from(dialog),
+            msi::Value::Int(50),
+            msi::Value::Int(50),
+            msi::Value::Int(width),
+            msi::Value::Int(height),
+            msi::Value::Int(attributes),
+            msi::Value::from("[ProductName] Setup"),
+            msi::Value::from(first),
+            msi::Value::from(default),
+            msi::Value::from(cancel),
+        ]);
+    }
+
+    fn wizard_dialog(&mut self, dialog: &str, first: &str, default: &str) {
+        self.dialog(dialog, (WIZARD_WIDTH, WIZARD_HEIGHT), WIZARD_ATTRS, first, default, "Cancel");
+    }
+
+    // Adds a control with no property, text or tab order; use the
+    // `control_property`, `control_text` and `control_next` methods to set
+    // those afterwards.
+    fn control(&mut self, dialog: &str, control: &str, kind: &str,
+               (x, y, width, height): (i32, i32, i32, i32), attributes: i32) {
+        self.controls.push(vec![
+            msi::Value::from(dialog),
+            msi::Value::from(control),
+            msi::Value::from(kind),
+            msi::Value::Int(x),
+            msi::Value::Int(y),
+            msi::Value::Int(width),
+            msi::Value::Int(height),
+            msi::Value::Int(attributes),
+            msi::Value::Null,
+            msi::Value::Null,
+            msi::Value::Null,
+            msi::Value::Null,
+        ]);
+    }
+
+    // Sets one column of an already-added control's row.
+    fn set_control_column(&mut self, dialog: &str, control: &str, column: usize, value: &str) {
+        for row in self.controls.iter_mut() {
+            if row[0] == msi::Value::from(dialog) && row[1] == msi::Value::from(control) {
+                row[column] = msi::Value::from(value);
+            }
+        }
+    }
+
+    // Sets the property that `control` displays or edits.
+    fn control_property(&mut self, dialog: &str, control: &str, property: &str) {
+        self.set_control_column(dialog, control, 8, property);
+    }
+
+    // Sets the text shown by `control`.
+    fn control_text(&mut self, dialog: &str, control: &str, text: &str) {
+        self.set_control_column(dialog, control, 9, text);
+    }
+
+    // Changes the tab order so that `control` is followed by `next`.
+    fn control_next(&mut self, dialog: &str, control: &str, next: &str) {
+        self.set_control_column(dialog, control, 10, next);
+    }
+
+    fn text(&mut self, dialog: &str, control: &str, rect: (i32, i32, i32, i32), text: &str) {
+        self.control(dialog, control, "Text", rect, TEXT_ATTRS);
+        self.control_text(dialog, control, text);
+    }
+
+    // Adds the large title used by the first and last dialogs of the wizard.
+    fn big_title(&mut self, dialog: &str, title: &str) {
+        self.text(dialog, "Title", (20, 20, 330, 60), &format!("{{\\BigFont}}{}", title));
+    }
+
+    // Adds the title, description and dividing line across the top of the
+    // middle dialogs of the wizard.
+    fn banner(&mut self, dialog: &str, title: &str, description: &str) {
+        self.text(dialog, "Title", (15, 6, 340, 15), &format!("{{\\TitleFont}}{}", title));
+        self.text(dialog, "Description", (25, 23, 320, 15), description);
+        self.control(dialog, "BannerLine", "Line", (0, 44, WIZARD_WIDTH, 0), CONTROL_ATTR_VISIBLE);
+    }
+
+    // Adds the Back, Next and Cancel buttons along the bottom of a wizard
+    // dialog.  The Back button goes to the `back` dialog, or is disabled if
+    // there isn't one; it is up to the caller to add events for Next.
+    fn wizard_buttons(&mut self, dialog: &str, back: Option<&str>, next_text: &str,
+                      next_enabled: bool) {
+        self.control(dialog, "BottomLine", "Line", (0, 234, WIZARD_WIDTH, 0), CONTROL_ATTR_VISIBLE);
+        let back_attrs = if back.is_some() { ACTIVE_ATTRS } else { CONTROL_ATTR_VISIBLE };
+        self.control(dialog, "Back", "PushButton", (180, 243, 56, 17), back_attrs);
+        self.control_text(dialog, "Back", "&Back");
+        self.control_next(dialog, "Back", "Next");
+        let next_attrs = if next_enabled { ACTIVE_ATTRS } else { CONTROL_ATTR_VISIBLE };
+        self.control(dialog, "Next", "PushButton", (236, 243, 56, 17), next_attrs);
+        self.control_text(dialog, "Next", next_text);
+        self.control_next(dialog, "Next", "Cancel");
+        self.control(dialog, "Cancel", "PushButton", (304, 243, 56, 17), ACTIVE_ATTRS);
+        self.control_text(dialog, "Cancel", "Cancel");
+        self.control_next(dialog, "Cancel", "Back");
+        if let Some(back) = back {
+            self.event(dialog, "Back", "NewDialog", back, "1", 1);
+        }
+        self.event(dialog, "Cancel", "SpawnDialog", "CancelDlg", "1", 1);
+    }
+
+    fn event(&mut self, dialog: &str, control: &str, event: &str, argument: &str,
+             condition: &str, ordering: i32) {
+        self.events.push(vec![
+            msi::Value::from(dialog),
+            msi::Value::from(control),
+            msi::Value::from(event),
+            msi::Value::from(argument),
+            msi::Value::from(condition),
+            msi::Value::Int(ordering),
+        ]);
+    }
+
+    fn condition(&mut self, dialog: &str, control: &str, action: &str, condition: &str) {
+        self.conditions.push(vec![
+            msi::Value::from(dialog),
+            msi::Value::from(control),
+            msi::Value::from(action),
+            msi::Value::from(condition),
+        ]);
+    }
+
+    fn mapping(&mut self, dialog: &str, control: &str, event: &str, attribute: &str) {
+        self.mappings.push(vec![
+            msi::Value::from(dialog),
+            msi::Value::from(control),
+            msi::Value::from(event),
+            msi::Value::from(attribute),
+        ]);
+    }
+
+    fn write<F>(self, package: &mut msi::Package<F>) -> ::Result<()>
+        where F: Read + Write + Seek
+    {
+        package.create_table("Dialog", vec![
+            msi::Column::build("Dialog").primary_key().id_string(72),
+            msi::Column::build("HCentering").range(0, 100).int16(),
+            msi::Column::build("VCentering").range(0, 100).int16(),
+            msi::Column::build("Width").range(0, 0x7fff).int16(),
+            msi::Column::build("Height").range(0, 0x7fff).int16(),
+            msi::Column::build("Attributes").nullable().int32(),
+            msi::Column::build("Title").nullable().formatted_string(128),
+            msi::Column::build("Control_First").id_string(50),
+            msi::Column::build("Control_Default").nullable().id_string(50),
+            msi::Column::build("Control_Cancel").nullable().id_string(50),
+        ])?;
+        package.create_table("Control", vec![
+            msi::Column::build("Dialog_").primary_key()
+                .foreign_key("Dialog", 1).id_string(72),
+            msi::Column::build("Control").primary_key().id_string(50),
+            msi::Column::build("Type").id_string(20),
+            msi::Column::build("X").range(0, 0x7fff).int16(),
+            msi::Column::build("Y").range(0, 0x7fff).int16(),
+            msi::Column::build("Width").range(0, 0x7fff).int16(),
+            msi::Column::build("Height").range(0, 0x7fff).int16(),
+            msi::Column::build("Attributes").nullable().int32(),
+            msi::Column::build("Property").nullable().id_string(72),
+            msi::Column::build("Text").nullable().formatted_string(0),
+            msi::Column::build("Control_Next").nullable().id_string(50),
+            msi::Column::build("Help").nullable().text_string(50),
+        ])?;
+        package.create_table("ControlEvent", vec![
+            msi::Column::build("Dialog_").primary_key()
+                .foreign_key("Dialog", 1).id_string(72),
+            msi::Column::build("Control_").primary_key()
+                .foreign_key("Control", 2).id_string(50),
+            msi::Column::build("Event").primary_key().formatted_string(50),
+            msi::Column::build("Argument").primary_key().formatted_string(255),
+            msi::Column::build("Condition").primary_key().nullable()
+                .category(msi::Category::Condition).string(255),
+            msi::Column::build("Ordering").nullable().range(0, 0x7fff).int16(),
+        ])?;
+        package.create_table("ControlCondition", vec![
+            msi::Column::build("Dialog_").primary_key()
+                .foreign_key("Dialog", 1).id_string(72),
+            msi::Column::build("Control_").primary_key()
+                .foreign_key("Control", 2).id_string(50),
+            msi::Column::build("Action").primary_key().text_string(50),
+            msi::Column::build("Condition").primary_key()
+                .category(msi::Category::Condition).string(255),
+        ])?;
+        package.create_table("EventMapping", vec![
+            msi::Column::build("Dialog_").primary_key()
+                .foreign_key("Dialog", 1).id_string(72),
+            msi::Column::build("Control_").primary_key()
+                .foreign_key("Control", 2).id_string(50),
+            msi::Column::build("Event").primary_key().id_string(50),
+            msi::Column::build("Attribute").id_string(50),
+        ])?;
+        package.create_table("TextStyle", vec![
+            msi::Column::build("TextStyle").primary_key().id_string(72),
+            msi::Column::build("FaceName").text_string(32),
+            msi::Column::build("Size").range(0, 0x7fff).int16(),
+            msi::Column::build("Color").nullable().range(0, 0xffffff).int32(),
+            msi::Column::build("StyleBits").nullable().range(0, 15).int16(),
+        ])?;
+        package.create_table("CheckBox", vec![
+            msi::Column::build("Property").primary_key().id_string(72),
+            msi::Column::build("Value").nullable().formatted_string(64),
+        ])?;
+        package.insert_rows(msi::Insert::into("Dialog").rows(self.dialogs))?;
+        package.insert_rows(msi::Insert::into("Control").rows(self.controls))?;
+        package.insert_rows(msi::Insert::into("ControlEvent").rows(self.events))?;
+        package.insert_rows(msi::Insert::into("ControlCondition").rows(self.conditions))?;
+        package.insert_rows(msi::Insert::into("EventMapping").rows(self.mappings))?;
+        let text_styles = TEXT_STYLES.iter().map(|&(name, face, size, style_bits)| {
+            vec![
+                msi::Value::from(name),
+                msi::Value::from(face),
+                msi::Value::Int(size),
+                msi::Value::Null,
+                msi::Value::Int(style_bits),
+            ]
+        }).collect();
+        package.insert_rows(msi::Insert::into("TextStyle").rows(text_styles))?;
+        package.insert_rows(msi::Insert::into("CheckBox").row(vec![
+            msi::Value::from(LICENSE_ACCEPTED_PROPERTY),
+            msi::Value::from("1"),
+        ]))?;
+        package.insert_rows(msi::Insert::into("Property").row(vec![
+            msi::Value::from("DefaultUIFont"),
+            msi::Value::from(TEXT_STYLES[0].0),
+        ]).row(vec![
+            msi::Value::from("ErrorDialog"),
+            msi::Value::from("ErrorDlg"),
+        ]))?;
+        // Show the wizard for new installs, just before the installation
+        // itself is executed; negative sequence numbers are for the dialogs
+        // shown at the end of a successful, cancelled or failed installation.
+        package.insert_rows(msi::Insert::into("InstallUISequence").row(vec![
+            msi::Value::from("WelcomeDlg"),
+            msi::Value::from("NOT Installed"),
+            msi::Value::Int(1297),
+        ]).row(vec![
+            msi::Value::from("ProgressDlg"),
+            msi::Value::Null,
+            msi::Value::Int(1299),
+        ]).row(vec![
+            msi::Value::from("ExitDialog"),
+            msi::Value::Null,
+            msi::Value::Int(-1),
+        ]).row(vec![
+            msi::Value::from("UserExit"),
+            msi::Value::Null,
+            msi::Value::Int(-2),
+        ]).row(vec![
+            msi::Value::from("FatalError"),
+            msi::Value::Null,
+            msi::Value::Int(-3),
+        ]))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::text_to_rtf;
+
+    #[test]
+    fn plain_text_to_rtf() {
+        let rtf = text_to_rtf("Copyright {C} \\ Jane Dö\r\nAll rights reserved.\n");
+        assert!(rtf.starts_with("{\\rtf1"));
+        assert!(rtf.ends_with("}\n"));
+        assert!(rtf.contains("Copyright \\{C\\} \\\\ Jane D\\u246?\\par\n\
+                              All rights reserved.\\par\n"));
+    }
+}
@@ -0,0 +1,368 @@
+// Offline validation for the MSI packages that we generate, so that
+// malformed packages are caught when bundling rather than when someone tries
+// to install them on Windows.  The checks are a subset of the Internal
+// Consistency Evaluators (ICEs) that Microsoft's validation tools run:
+//
+// * every required table (including the sequence tables) exists, and every
+//   required property is set;
+// * every foreign key refers to an existing row (ICE03);
+// * every GUID is a valid, uppercase GUID (ICE03), and no two components
+//   share a GUID (ICE08);
+// * File sequence numbers are unique and covered by the Media table, and
+//   every File is stored exactly once in the cabinet that its Media entry
+//   names, under its File key.
+//
+// For more information about ICEs, see
+// https://docs.microsoft.com/en-us/windows/win32/msi/ice-reference
+
+use cab;
+use msi;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+// Tables that every package we generate must have:
+const REQUIRED_TABLES: &[&str] = &[
+    "AdminExecuteSequence",
+    "AdminUISequence",
+    "AdvtExecuteSequence",
+    "Component",
+    "Directory",
+    "Feature",
+    "FeatureComponents",
+    "File",
+    "InstallExecuteSequence",
+    "InstallUISequence",
+    "Media",
+    "Property",
+];
+
+// Properties that every package must set:
+const REQUIRED_PROPERTIES: &[&str] = &[
+    "Manufacturer",
+    "ProductCode",
+    "ProductLanguage",
+    "ProductName",
+    "ProductVersion",
+    "UpgradeCode",
+];
+
+// Properties whose values must be GUIDs:
+const GUID_PROPERTIES: &[&str] = &["ProductCode", "UpgradeCode"];
+
+// The foreign keys in the tables that we generate, as (table, column,
+// referenced table, referenced column):
+const FOREIGN_KEYS: &[(&str, &str, &str, &str)] = &[
+    ("Component", "Directory_", "Directory", "Directory"),
+    ("Control", "Dialog_", "Dialog", "Dialog"),
+    ("ControlCondition", "Control_", "Control", "Control"),
+    ("ControlCondition", "Dialog_", "Dialog", "Dialog"),
+    ("ControlEvent", "Control_", "Control", "Control"),
+    ("ControlEvent", "Dialog_", "Dialog", "Dialog"),
+    ("Directory", "Directory_Parent", "Directory", "Directory"),
+    ("Environment", "Component_", "Component", "Component"),
+    ("EventMapping", "Control_", "Control", "Control"),
+    ("EventMapping", "Dialog_", "Dialog", "Dialog"),
+    ("Extension", "Component_", "Component", "Component"),
+    ("Extension", "Feature_", "Feature", "Feature"),
+    ("Extension", "MIME_", "MIME", "ContentType"),
+    ("Extension", "ProgId_", "ProgId", "ProgId"),
+    ("Feature", "Directory_", "Directory", "Directory"),
+    ("Feature", "Feature_Parent", "Feature", "Feature"),
+    ("FeatureComponents", "Component_", "Component", "Component"),
+    ("FeatureComponents", "Feature_", "Feature", "Feature"),
+    ("File", "Component_", "Component", "Component"),
+    ("MIME", "Extension_", "Extension", "Extension"),
+    ("ProgId", "Icon_", "Icon", "Name"),
+    ("ProgId", "ProgId_Parent", "ProgId", "ProgId"),
+    ("Registry", "Component_", "Component", "Component"),
+    ("Shortcut", "Component_", "Component", "Component"),
+    ("Shortcut", "Directory_", "Directory", "Directory"),
+    ("Shortcut", "Icon_", "Icon", "Name"),
+    ("Verb", "Extension_", "Extension", "Extension"),
+];
+
+/// Reopens the MSI package at `path` and checks it for problems that would
+/// make it fail to install, returning an error that lists them if there are
+/// any.
+pub fn validate_package(path: &Path) -> ::Result<()> {
+    let mut package = msi::Package::open(fs::File::open(path)?)?;
+    let problems = find_problems(&mut package)?;
+    if !problems.is_empty() {
+        bail!("{} problem(s) found:\n    {}", problems.len(), problems.join("\n    "));
+    }
+    Ok(())
+}
+
+// Runs all the checks on the package, returning a description of each
+// problem found.
+fn find_problems<F>(package: &mut msi::Package<F>) -> ::Result<Vec<String>>
+    where F: Read + Write + Seek
+{
+    let mut problems = Vec::new();
+    for table in REQUIRED_TABLES.iter() {
+        if !package.has_table(table) {
+            problems.push(format!("Missing required table {}", table));
+        }
+    }
+    // The remaining checks assume the core tables exist.
+    if !problems.is_empty() {
+        return Ok(problems);
+    }
+    check_properties(package, &mut problems)?;
+    check_foreign_keys(package, &mut problems)?;
+    check_guids(package, &mut problems)?;
+    check_files(package, &mut problems)?;
+    Ok(problems)
+}
+
+// Checks that the required properties are set, and that GUID-valued
+// properties are valid.
+fn check_properties<F>(package: &mut msi::Package<F>, problems: &mut Vec<String>)
+                       -> ::Result<()>
+    where F: Read + Write + Seek
+{
+    let properties: HashMap<String, String> = package
+        .select_rows(msi::Select::table("Property"))?
+        .filter_map(|row| {
+            match (value_string(&row["Property"]), value_string(&row["Value"])) {
+                (Some(property), Some(value)) => Some((property, value)),
+                _ => None,
+            }
+        })
+        .collect();
+    for property in REQUIRED_PROPERTIES.iter() {
+        if !properties.contains_key(*property) {
+            problems.push(format!("Missing required property {}", property));
+        }
+    }
+    for property in GUID_PROPERTIES.iter() {
+        if let Some(value) = properties.get(*property) {
+            if !is_valid_guid(value) {
+                problems.push(format!("Property {} is not a valid GUID: {:?}", property, value));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks that every non-null value in a foreign key column matches a row in
+// the table that it refers to.
+fn check_foreign_keys<F>(package: &mut msi::Package<F>, problems: &mut Vec<String>)
+                         -> ::Result<()>
+    where F: Read + Write + Seek
+{
+    for &(table, column, ref_table, ref_column) in FOREIGN_KEYS.iter() {
+        if !package.has_table(table) {
+            continue;
+        }
+        let values = column_values(package, table, column)?;
+        if values.is_empty() {
+            continue;
+        }
+        if !package.has_table(ref_table) {
+            problems.push(format!("{}.{} refers to missing table {}", table, column, ref_table));
+            continue;
+        }
+        let keys: HashSet<String> =
+            column_values(package, ref_table, ref_column)?.into_iter().collect();
+        for value in values {
+            if !keys.contains(&value) {
+                problems.push(format!("{}.{} refers to {:?}, which is not in {}.{}",
+                                      table, column, value, ref_table, ref_column));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks that every value in a GUID column is a valid GUID, and that no two
+// components have the same GUID.
+fn check_guids<F>(package: &mut msi::Package<F>, problems: &mut Vec<String>) -> ::Result<()>
+    where F: Read + Write + Seek
+{
+    let mut guid_columns = Vec::new();
+    for table in package.tables() {
+        for column in table.columns() {
+            if column.category() == Some(msi::Category::Guid) {
+                guid_columns.push((table.name().to_string(), column.name().to_string()));
+            }
+        }
+    }
+    for (table, column) in guid_columns {
+        for value in column_values(package, &table, &column)? {
+            if !is_valid_guid(&value) {
+                problems.push(format!("{}.{} is not a valid GUID: {:?}", table, column, value));
+            }
+        }
+    }
+    let mut components_by_guid = HashMap::<String, String>::new();
+    for row in package.select_rows(msi::Select::table("Component"))? {
+        let component = value_string(&row["Component"]).unwrap_or_default();
+        if let Some(guid) = value_string(&row["ComponentId"]) {
+            if let Some(other) = components_by_guid.insert(guid.clone(), component.clone()) {
+                problems.push(format!("Components {} and {} have the same GUID {}",
+                                      other, component, guid));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks that each File has a unique sequence number that falls within the
+// range of a Media entry, and that each file is stored exactly once, in the
+// cabinet for that Media entry.
+fn check_files<F>(package: &mut msi::Package<F>, problems: &mut Vec<String>) -> ::Result<()>
+    where F: Read + Write + Seek
+{
+    let mut files_by_sequence = BTreeMap::<i32, String>::new();
+    for row in package.select_rows(msi::Select::table("File"))? {
+        let file = value_string(&row["File"]).unwrap_or_default();
+        let sequence = row["Sequence"].as_int().unwrap_or(0);
+        if let Some(other) = files_by_sequence.insert(sequence, file.clone()) {
+            problems.push(format!("Files {} and {} have the same sequence number {}",
+                                  other, file, sequence));
+        }
+    }
+    let mut media: Vec<(i32, Option<String>)> = package
+        .select_rows(msi::Select::table("Media"))?
+        .map(|row| (row["LastSequence"].as_int().unwrap_or(0), value_string(&row["Cabinet"])))
+        .collect();
+    media.sort_by_key(|&(last_sequence, _)| last_sequence);
+
+    // Each Media entry covers the sequence numbers after the previous
+    // entry's, up to its LastSequence.
+    let mut cabinets_by_file = HashMap::<String, String>::new();
+    let mut first_sequence = 1;
+    for (last_sequence, cabinet) in media {
+        let expected: HashSet<&String> =
+            files_by_sequence.range(first_sequence..last_sequence + 1)
+                .map(|(_, file)| file)
+                .collect();
+        first_sequence = last_sequence + 1;
+        // Cabinets whose names start with `#` are stored as streams in the
+        // package; we never generate external cabinets.
+        let stream_name = match cabinet {
+            Some(ref cabinet) if cabinet.starts_with('#') => cabinet[1..].to_string(),
+            _ => continue,
+        };
+        if !package.has_stream(&stream_name) {
+            problems.push(format!("Missing cabinet stream {:?}", stream_name));
+            continue;
+        }
+        let names: Vec<String> = {
+            let cabinet = cab::Cabinet::new(package.read_stream(&stream_name)?)?;
+            cabinet.folder_entries()
+                .flat_map(|folder| folder.file_entries())
+                .map(|file| file.name().to_string())
+                .collect()
+        };
+        for name in names {
+            if let Some(other) = cabinets_by_file.insert(name.clone(), stream_name.clone()) {
+                problems.push(format!("File {} is stored in both cabinet {:?} and cabinet {:?}",
+                                      name, other, stream_name));
+            } else if !expected.contains(&name) {
+                problems.push(format!("Cabinet {:?} contains {}, which is not one of its \
+                                       files in the File table", stream_name, name));
+            }
+        }
+    }
+    if let Some((&sequence, file)) = files_by_sequence.range(first_sequence..).next() {
+        problems.push(format!("File {} has sequence number {}, which no Media entry covers",
+                              file, sequence));
+    }
+    for file in files_by_sequence.values() {
+        if !cabinets_by_file.contains_key(file) {
+            problems.push(format!("File {} is not stored in any cabinet", file));
+        }
+    }
+    Ok(())
+}
+
+// Returns the non-null values in the given column of a table, as strings (or
+// nothing, if the table has no such column).
+fn column_values<F>(package: &mut msi::Package<F>, table: &str, column: &str)
+                    -> ::Result<Vec<String>>
+    where F: Read + Write + Seek
+{
+    if !package.get_table(table).is_some_and(|table| table.has_column(column)) {
+        return Ok(Vec::new());
+    }
+    let rows = package.select_rows(msi::Select::table(table))?;
+    Ok(rows.filter_map(|row| value_string(&row[column])).collect())
+}
+
+fn value_string(value: &msi::Value) -> Option<String> {
+    match *value {
+        msi::Value::Null => None,
+        msi::Value::Int(number) => Some(number.to_string()),
+        msi::Value::Str(ref string) => Some(string.clone()),
+    }
+}
+
+// Returns true if `string` is a GUID in the form that Windows Installer
+// requires, e.g. `{6E5B0C5B-3F3A-4B4A-8E8E-1B3F2A9D7C11}` (with braces, and
+// with uppercase hex digits).
+fn is_valid_guid(string: &str) -> bool {
+    let bytes = string.as_bytes();
+    bytes.len() == 38 && bytes[0] == b'{' && bytes[37] == b'}' &&
+        bytes[1..37].iter().enumerate().all(|(index, &byte)| {
+            match index {
+                8 | 13 | 18 | 23 => byte == b'-',
+                _ => byte.is_ascii_digit() || (b'A'..=b'F').contains(&byte),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_foreign_keys, find_problems, is_valid_guid};
+    use msi;
+    use std::io::Cursor;
+
+    #[test]
+    fn guid_format() {
+        assert!(is_valid_guid("{6E5B0C5B-3F3A-4B4A-8E8E-1B3F2A9D7C11}"));
+        assert!(!is_valid_guid("{6e5b0c5b-3f3a-4b4a-8e8e-1b3f2a9d7c11}"));
+        assert!(!is_valid_guid("6E5B0C5B-3F3A-4B4A-8E8E-1B3F2A9D7C11"));
+        assert!(!is_valid_guid("{6E5B0C5B3F3A-4B4A-8E8E-1B3F2A9D7C11-}"));
+    }
+
+    #[test]
+    fn missing_tables() {
+        let cursor = Cursor::new(Vec::new());
+        let mut package = msi::Package::create(msi::PackageType::Installer, cursor).unwrap();
+        let problems = find_problems(&mut package).unwrap();
+        assert!(problems.contains(&"Missing required table InstallExecuteSequence".to_string()));
+    }
+
+    #[test]
+    fn dangling_foreign_keys() {
+        let cursor = Cursor::new(Vec::new());
+        let mut package = msi::Package::create(msi::PackageType::Installer, cursor).unwrap();
+        package.create_table("Component", vec![
+            msi::Column::build("Component").primary_key().id_string(72),
+        ]).unwrap();
+        package.create_table("Registry", vec![
+            msi::Column::build("Registry").primary_key().id_string(72),
+            msi::Column::build("Component_").foreign_key("Component", 1).id_string(72),
+        ]).unwrap();
+        package.insert_rows(msi::Insert::into("Component").row(vec![
+            msi::Value::from("INSTALLDIR"),
+        ])).unwrap();
+        package.insert_rows(msi::Insert::into("Registry").row(vec![
+            msi::Value::from("reg0000"),
+            msi::Value::from("INSTALLDIR"),
+        ]).row(vec![
+            msi::Value::from("reg0001"),
+            msi::Value::from("NoSuchComponent"),
+        ])).unwrap();
+        let mut problems = Vec::new();
+        check_foreign_keys(&mut package, &mut problems).unwrap();
+        assert_eq!(problems, vec![
+            "Registry.Component_ refers to \"NoSuchComponent\", which is not in \
+             Component.Component".to_string(),
+        ]);
+    }
+}
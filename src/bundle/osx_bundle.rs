@@ -18,8 +18,9 @@
 // files into the `Contents` directory of the bundle.
 
 use super::common;
-use {ResultExt, Settings};
-use chrono;
+use super::settings::FileAssociation;
+use {PackageType, ResultExt, Settings};
+use chrono::{self, TimeZone};
 use dirs;
 use icns;
 use image::{self, GenericImage};
@@ -60,9 +61,8 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
         "Failed to bundle frameworks"
     })?;
 
-    for src in settings.resource_files() {
-        let src = src?;
-        let dest = resources_dir.join(common::resource_relpath(&src));
+    for (src, dest) in settings.resource_files(PackageType::OsxBundle)? {
+        let dest = resources_dir.join(dest);
         common::copy_file(&src, &dest).chain_err(|| {
             format!("Failed to copy resource file {:?}", src)
         })?;
@@ -75,6 +75,38 @@ pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
     Ok(vec![app_bundle_path])
 }
 
+// Writes the CFBundleDocumentTypes entry of an Info.plist file for the given
+// file associations, if there are any.
+fn write_document_types<W: Write>(file: &mut W, associations: &[FileAssociation])
+                                  -> ::Result<()> {
+    if associations.is_empty() {
+        return Ok(());
+    }
+    writeln!(file, "  <key>CFBundleDocumentTypes</key>\n  <array>")?;
+    for association in associations {
+        writeln!(file, "    <dict>")?;
+        writeln!(file, "      <key>CFBundleTypeExtensions</key>\n      <array>")?;
+        for extension in association.extensions.iter() {
+            writeln!(file, "        <string>{}</string>", common::xml_escape(extension))?;
+        }
+        writeln!(file, "      </array>")?;
+        if let Some(ref mime_type) = association.mime_type {
+            writeln!(file,
+                     "      <key>CFBundleTypeMIMETypes</key>\n      \
+                      <array>\n        <string>{}</string>\n      </array>",
+                     common::xml_escape(mime_type))?;
+        }
+        writeln!(file,
+                 "      <key>CFBundleTypeName</key>\n      <string>{}</string>",
+                 common::xml_escape(&association.display_name()))?;
+        writeln!(file,
+                 "      <key>CFBundleTypeRole</key>\n      <string>Editor</string>")?;
+        writeln!(file, "    </dict>")?;
+    }
+    writeln!(file, "  </array>")?;
+    Ok(())
+}
+
 fn copy_binary_to_bundle(bundle_directory: &Path, settings: &Settings) -> ::Result<()> {
     let dest_dir = bundle_directory.join("MacOS");
     common::copy_file(settings.binary_path(),
@@ -83,7 +115,11 @@ fn copy_binary_to_bundle(bundle_directory: &Path, settings: &Settings) -> ::Resu
 
 fn create_info_plist(bundle_dir: &Path, bundle_icon_file: Option<PathBuf>,
                      settings: &Settings) -> ::Result<()> {
-    let build_number = chrono::Utc::now().format("%Y%m%d.%H%M%S");
+    let build_time = match settings.source_date_epoch() {
+        Some(epoch) => chrono::Utc.timestamp_opt(epoch as i64, 0).unwrap(),
+        None => chrono::Utc::now(),
+    };
+    let build_number = build_time.format("%Y%m%d.%H%M%S");
     let file = &mut common::create_file(&bundle_dir.join("Info.plist"))?;
     write!(file,
            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
@@ -97,6 +133,7 @@ fn create_info_plist(bundle_dir: &Path, bundle_icon_file: Option<PathBuf>,
     write!(file,
            "  <key>CFBundleDisplayName</key>\n  <string>{}</string>\n",
            settings.bundle_name())?;
+    write_document_types(file, settings.file_associations())?;
     write!(file,
            "  <key>CFBundleExecutable</key>\n  <string>{}</string>\n",
            settings.binary_name())?;
@@ -118,8 +155,10 @@ fn create_info_plist(bundle_dir: &Path, bundle_icon_file: Option<PathBuf>,
            "  <key>CFBundlePackageType</key>\n  <string>APPL</string>\n")?;
     write!(file,
            "  <key>CFBundleShortVersionString</key>\n  <string>{}</string>\n",
-           settings.version_string())?;
-    if !settings.osx_url_schemes().is_empty() {
+           settings.version()?.short_version_string()?)?;
+    let url_schemes: Vec<&String> =
+        settings.url_schemes().iter().chain(settings.osx_url_schemes()).collect();
+    if !url_schemes.is_empty() {
         write!(file,
             "  <key>CFBundleURLTypes</key>\n  \
                <array>\n    \
@@ -131,7 +170,7 @@ fn create_info_plist(bundle_dir: &Path, bundle_icon_file: Option<PathBuf>,
                        <key>CFBundleURLSchemes</key>\n      \
                        <array>\n",
             settings.bundle_name())?;
-        for scheme in settings.osx_url_schemes() {
+        for scheme in url_schemes {
             write!(file, "        <string>{}</string>\n", scheme)?;
         }
         write!(file,
@@ -276,7 +315,7 @@ fn create_icns_file(resources_dir: &PathBuf, settings: &Settings)
     }
 
     for (icon, next_size_down, density) in images_to_resize {
-        let icon = icon.resize_exact(next_size_down, next_size_down, image::Lanczos3);
+        let icon = common::resize_icon(&icon, next_size_down);
         add_icon_to_family(icon, density, &mut family)?;
     }
 
@@ -307,3 +346,30 @@ fn make_icns_image(img: image::DynamicImage) -> io::Result<icns::Image> {
     };
     icns::Image::from_data(pixel_format, img.width(), img.height(), img.raw_pixels())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::write_document_types;
+    use bundle::settings::FileAssociation;
+
+    #[test]
+    fn document_types() {
+        let association = FileAssociation {
+            extensions: vec!["foo".to_string()],
+            mime_type: Some("application/x-foo".to_string()),
+            description: Some("Foo & Bar <document>".to_string()),
+        };
+        let mut output = Vec::new();
+        write_document_types(&mut output, &[association]).unwrap();
+        let plist = String::from_utf8(output).unwrap();
+        assert!(plist.starts_with("  <key>CFBundleDocumentTypes</key>\n  <array>\n    <dict>\n"));
+        assert!(plist.contains("\n        <string>foo</string>\n"));
+        assert!(plist.contains("\n        <string>application/x-foo</string>\n"));
+        assert!(plist.contains("<key>CFBundleTypeName</key>\n      \
+                                <string>Foo &amp; Bar &lt;document&gt;</string>\n"));
+        assert!(plist.ends_with("\n    </dict>\n  </array>\n"));
+        let mut output = Vec::new();
+        write_document_types(&mut output, &[]).unwrap();
+        assert!(output.is_empty());
+    }
+}
@@ -1,6 +1,615 @@
-use Settings;
-use std::path::PathBuf;
+// The structure of an RPM package looks something like this:
+//
+// foobar-1.2.3-1.x86_64.rpm
+//     lead            # Fixed-size legacy header identifying the file as an RPM
+//     signature       # Header structure with the size and digests of the rest
+//     header          # Header structure with the package metadata, the
+//                     # dependencies and the list of installed files
+//     payload         # gzip-compressed cpio archive of the files to install:
+//         ./usr/bin/foobar                            # Binary executable file
+//         ./usr/share/applications/foobar.desktop     # Desktop file (for apps)
+//         ./usr/share/icons/hicolor/...               # Icon files (for apps)
+//         ./usr/lib/foobar/...                        # Other resource files
+//
+// The installed files are laid out exactly as for the deb bundle.  For more
+// information about the file format, see
+// https://rpm-software-management.github.io/rpm/manual/format.html and
+// http://ftp.rpm.org/max-rpm/s1-rpm-file-format-rpm-file-format.html.
 
-pub fn bundle_project(_settings: &Settings) -> ::Result<Vec<PathBuf>> {
-    unimplemented!();
+use super::common;
+use super::deb_bundle;
+use {PackageType, ResultExt, Settings};
+use md5;
+use sha2::{Digest, Sha256};
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+// The release number of the package; cargo-bundle always builds the first
+// release of any given version.
+const RELEASE: &str = "1";
+
+const LEAD_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
+const HEADER_MAGIC: [u8; 8] = [0x8e, 0xad, 0xe8, 0x01, 0x00, 0x00, 0x00, 0x00];
+
+// Data types of header entries:
+const TYPE_INT16: u32 = 3;
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_BIN: u32 = 7;
+const TYPE_STRING_ARRAY: u32 = 8;
+const TYPE_I18NSTRING: u32 = 9;
+
+// Tags marking the region of a header that is covered by its digests:
+const RPMTAG_HEADERSIGNATURES: u32 = 62;
+const RPMTAG_HEADERIMMUTABLE: u32 = 63;
+
+// Tags used in the signature header:
+const RPMSIGTAG_SIZE: u32 = 1000;
+const RPMSIGTAG_MD5: u32 = 1004;
+const RPMSIGTAG_PAYLOADSIZE: u32 = 1007;
+const RPMSIGTAG_SHA256: u32 = 273;
+
+// Tags used in the main header:
+const RPMTAG_HEADERI18NTABLE: u32 = 100;
+const RPMTAG_NAME: u32 = 1000;
+const RPMTAG_VERSION: u32 = 1001;
+const RPMTAG_RELEASE: u32 = 1002;
+const RPMTAG_SUMMARY: u32 = 1004;
+const RPMTAG_DESCRIPTION: u32 = 1005;
+const RPMTAG_BUILDTIME: u32 = 1006;
+const RPMTAG_BUILDHOST: u32 = 1007;
+const RPMTAG_SIZE: u32 = 1009;
+const RPMTAG_LICENSE: u32 = 1014;
+const RPMTAG_PACKAGER: u32 = 1015;
+const RPMTAG_GROUP: u32 = 1016;
+const RPMTAG_URL: u32 = 1020;
+const RPMTAG_OS: u32 = 1021;
+const RPMTAG_ARCH: u32 = 1022;
+const RPMTAG_FILESIZES: u32 = 1028;
+const RPMTAG_FILEMODES: u32 = 1030;
+const RPMTAG_FILERDEVS: u32 = 1033;
+const RPMTAG_FILEMTIMES: u32 = 1034;
+const RPMTAG_FILEDIGESTS: u32 = 1035;
+const RPMTAG_FILELINKTOS: u32 = 1036;
+const RPMTAG_FILEFLAGS: u32 = 1037;
+const RPMTAG_FILEUSERNAME: u32 = 1039;
+const RPMTAG_FILEGROUPNAME: u32 = 1040;
+const RPMTAG_SOURCERPM: u32 = 1044;
+const RPMTAG_FILEVERIFYFLAGS: u32 = 1045;
+const RPMTAG_PROVIDENAME: u32 = 1047;
+const RPMTAG_REQUIREFLAGS: u32 = 1048;
+const RPMTAG_REQUIRENAME: u32 = 1049;
+const RPMTAG_REQUIREVERSION: u32 = 1050;
+const RPMTAG_FILEDEVICES: u32 = 1095;
+const RPMTAG_FILEINODES: u32 = 1096;
+const RPMTAG_FILELANGS: u32 = 1097;
+const RPMTAG_PROVIDEFLAGS: u32 = 1112;
+const RPMTAG_PROVIDEVERSION: u32 = 1113;
+const RPMTAG_DIRINDEXES: u32 = 1116;
+const RPMTAG_BASENAMES: u32 = 1117;
+const RPMTAG_DIRNAMES: u32 = 1118;
+const RPMTAG_PAYLOADFORMAT: u32 = 1124;
+const RPMTAG_PAYLOADCOMPRESSOR: u32 = 1125;
+const RPMTAG_PAYLOADFLAGS: u32 = 1126;
+const RPMTAG_FILEDIGESTALGO: u32 = 5011;
+const RPMTAG_PAYLOADDIGEST: u32 = 5092;
+const RPMTAG_PAYLOADDIGESTALGO: u32 = 5093;
+
+// Dependency flags:
+const RPMSENSE_LESS: u32 = 0x02;
+const RPMSENSE_EQUAL: u32 = 0x08;
+const RPMSENSE_RPMLIB: u32 = 0x0100_0000;
+
+// The gzip compression level used for the payload:
+const PAYLOAD_COMPRESSION_LEVEL: u32 = 9;
+
+// The OpenPGP hash algorithm identifier for SHA-256:
+const PGPHASHALGO_SHA256: u32 = 8;
+
+// Features of the rpm tool itself that packages built by cargo-bundle need.
+const RPMLIB_REQUIREMENTS: &[(&str, &str)] = &[
+    ("rpmlib(CompressedFileNames)", "3.0.4-1"),
+    ("rpmlib(FileDigests)", "4.6.0-1"),
+    ("rpmlib(PayloadFilesHavePrefix)", "4.0-1"),
+];
+
+// Info about a file (or directory) to be installed by the package.
+struct FileInfo {
+    // The path to the existing file that will be added to the payload.
+    source_path: PathBuf,
+    // The directory this file will be installed into, with a trailing slash.
+    dirname: String,
+    // The name of this file in the filesystem.
+    basename: String,
+    // The file type and permission bits for the installed file.
+    mode: u16,
+    // The size of this file, in bytes.
+    size: u64,
+    // The modification time of this file, in seconds since the epoch.
+    mtime: u32,
+    // Hex-encoded SHA-256 digest of the file contents (empty for directories).
+    digest: String,
+}
+
+impl FileInfo {
+    fn install_path(&self) -> String {
+        format!("{}{}", self.dirname, self.basename)
+    }
+
+    fn is_dir(&self) -> bool {
+        self.mode & 0o170000 == 0o040000
+    }
+}
+
+// A value stored in a header entry.
+enum HeaderValue {
+    Int16(Vec<u16>),
+    Int32(Vec<u32>),
+    String(String),
+    Binary(Vec<u8>),
+    StringArray(Vec<String>),
+    I18nString(String),
+}
+
+impl HeaderValue {
+    fn data_type(&self) -> u32 {
+        match *self {
+            HeaderValue::Int16(_) => TYPE_INT16,
+            HeaderValue::Int32(_) => TYPE_INT32,
+            HeaderValue::String(_) => TYPE_STRING,
+            HeaderValue::Binary(_) => TYPE_BIN,
+            HeaderValue::StringArray(_) => TYPE_STRING_ARRAY,
+            HeaderValue::I18nString(_) => TYPE_I18NSTRING,
+        }
+    }
+
+    fn alignment(&self) -> usize {
+        match *self {
+            HeaderValue::Int16(_) => 2,
+            HeaderValue::Int32(_) => 4,
+            _ => 1,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match *self {
+            HeaderValue::Int16(ref values) => values.len() as u32,
+            HeaderValue::Int32(ref values) => values.len() as u32,
+            HeaderValue::Binary(ref bytes) => bytes.len() as u32,
+            HeaderValue::StringArray(ref strings) => strings.len() as u32,
+            HeaderValue::String(_) | HeaderValue::I18nString(_) => 1,
+        }
+    }
+
+    fn write_to(&self, store: &mut Vec<u8>) {
+        match *self {
+            HeaderValue::Int16(ref values) => {
+                for value in values {
+                    store.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            HeaderValue::Int32(ref values) => {
+                for value in values {
+                    store.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            HeaderValue::Binary(ref bytes) => store.extend_from_slice(bytes),
+            HeaderValue::String(ref string) | HeaderValue::I18nString(ref string) => {
+                store.extend_from_slice(string.as_bytes());
+                store.push(0);
+            }
+            HeaderValue::StringArray(ref strings) => {
+                for string in strings {
+                    store.extend_from_slice(string.as_bytes());
+                    store.push(0);
+                }
+            }
+        }
+    }
+}
+
+// A header structure, as used for both the signature and the main header of
+// the package.  Entries are kept sorted by tag.
+struct Header {
+    entries: BTreeMap<u32, HeaderValue>,
+}
+
+impl Header {
+    fn new() -> Header {
+        Header { entries: BTreeMap::new() }
+    }
+
+    fn add(&mut self, tag: u32, value: HeaderValue) {
+        self.entries.insert(tag, value);
+    }
+
+    // Serializes the header, marking all of its entries as belonging to a
+    // single region with the given tag.
+    fn to_bytes(&self, region_tag: u32) -> Vec<u8> {
+        let num_entries = self.entries.len() as u32 + 1;
+        let mut index = Vec::new();
+        let mut store = Vec::new();
+        for (&tag, value) in self.entries.iter() {
+            while store.len() % value.alignment() != 0 {
+                store.push(0);
+            }
+            push_u32(&mut index, tag);
+            push_u32(&mut index, value.data_type());
+            push_u32(&mut index, store.len() as u32);
+            push_u32(&mut index, value.count());
+            value.write_to(&mut store);
+        }
+        // The region trailer is a copy of the region's index entry, whose
+        // offset is the (negated) size of the index entries it covers.
+        let trailer_offset = store.len() as u32;
+        push_u32(&mut store, region_tag);
+        push_u32(&mut store, TYPE_BIN);
+        push_u32(&mut store, (-16 * num_entries as i32) as u32);
+        push_u32(&mut store, 16);
+        let mut bytes = HEADER_MAGIC.to_vec();
+        push_u32(&mut bytes, num_entries);
+        push_u32(&mut bytes, store.len() as u32);
+        push_u32(&mut bytes, region_tag);
+        push_u32(&mut bytes, TYPE_BIN);
+        push_u32(&mut bytes, trailer_offset);
+        push_u32(&mut bytes, 16);
+        bytes.extend_from_slice(&index);
+        bytes.extend_from_slice(&store);
+        bytes
+    }
+}
+
+pub fn bundle_project(settings: &Settings) -> ::Result<Vec<PathBuf>> {
+    let arch = match settings.binary_arch() {
+        "x86" => "i686",
+        "arm" => "armv7hl",
+        other => other,
+    };
+    let name = str::replace(settings.bundle_name(), " ", "-").to_ascii_lowercase();
+    let version = settings.version()?.linux_version();
+    let full_name = format!("{}-{}-{}", name, version, RELEASE);
+    let package_base_name = format!("{}.{}", full_name, arch);
+    let package_name = format!("{}.rpm", package_base_name);
+    common::print_bundling(&package_name)?;
+    let base_dir = settings.project_out_directory().join("bundle/rpm");
+    let package_dir = base_dir.join(&package_base_name);
+    if package_dir.exists() {
+        fs::remove_dir_all(&package_dir).chain_err(|| {
+            format!("Failed to remove old {}", package_base_name)
+        })?;
+    }
+    let package_path = base_dir.join(package_name);
+
+    // Generate data files, then the payload and headers describing them.
+    let data_dir = deb_bundle::generate_data(settings, PackageType::Rpm, &package_dir)?;
+    let resource_dir = Path::new("usr/lib").join(settings.binary_name());
+    let source_date_epoch = settings.source_date_epoch();
+    let files = collect_file_info(&data_dir, &resource_dir, source_date_epoch).chain_err(|| {
+        "Failed to collect file information"
+    })?;
+    let (payload, payload_size) = generate_payload(&files, source_date_epoch).chain_err(|| {
+        "Failed to create payload archive"
+    })?;
+    let header = generate_header(settings, &name, &version, arch, &files, &payload)
+        .to_bytes(RPMTAG_HEADERIMMUTABLE);
+    let signature = generate_signature(&header, &payload, payload_size)
+        .to_bytes(RPMTAG_HEADERSIGNATURES);
+
+    let mut file = common::create_file(&package_path)?;
+    write_lead(&mut file, &full_name, arch)?;
+    file.write_all(&signature)?;
+    // The signature is padded so that the main header is 8-byte aligned.
+    let padding = (8 - signature.len() % 8) % 8;
+    file.write_all(&[0; 8][..padding])?;
+    file.write_all(&header)?;
+    file.write_all(&payload)?;
+    file.flush()?;
+    Ok(vec![package_path])
+}
+
+/// Returns a `FileInfo` for each regular file within the `data_dir`, as well
+/// as for the directories under `resource_dir` (which belong to this package
+/// alone), sorted by install path.  Modification times are clamped to the
+/// `source_date_epoch` (if any).
+fn collect_file_info(data_dir: &Path, resource_dir: &Path, source_date_epoch: Option<u64>)
+                     -> ::Result<Vec<FileInfo>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(data_dir) {
+        let entry = entry?;
+        let path = entry.path();
+        let rel_path = path.strip_prefix(data_dir).unwrap();
+        let is_dir = entry.file_type().is_dir();
+        if is_dir && !rel_path.starts_with(resource_dir) {
+            continue;
+        }
+        let install_path = Path::new("/").join(rel_path);
+        let dirname = match install_path.parent().and_then(Path::to_str) {
+            Some("/") => "/".to_string(),
+            Some(parent) => format!("{}/", parent),
+            None => bail!("Non-UTF-8 path: {:?}", rel_path),
+        };
+        let basename = match install_path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_string(),
+            None => bail!("Non-UTF-8 path: {:?}", rel_path),
+        };
+        let metadata = entry.metadata()?;
+        let mut mtime = common::modified_time(&metadata);
+        if let Some(epoch) = source_date_epoch {
+            mtime = min(mtime, epoch);
+        }
+        let (mode, size, digest) = if is_dir {
+            (0o040755, 0, String::new())
+        } else {
+            let is_executable = rel_path.starts_with("usr/bin") || common::is_executable(&metadata);
+            let mode = if is_executable { 0o100755 } else { 0o100644 };
+            let mut hash = Sha256::new();
+            io::copy(&mut File::open(path)?, &mut hash)?;
+            (mode, metadata.len(), hex_string(&hash.finalize()))
+        };
+        files.push(FileInfo {
+            source_path: path.to_path_buf(),
+            dirname,
+            basename,
+            mode,
+            size,
+            mtime: mtime as u32,
+            digest,
+        });
+    }
+    files.sort_by_key(FileInfo::install_path);
+    Ok(files)
+}
+
+/// Creates the gzip-compressed cpio archive holding the given files, and
+/// returns it along with the size of the uncompressed archive.
+fn generate_payload(files: &[FileInfo], source_date_epoch: Option<u64>)
+                    -> ::Result<(Vec<u8>, u64)> {
+    let mut encoder = common::gzip_encoder(Vec::new(), PAYLOAD_COMPRESSION_LEVEL,
+                                           source_date_epoch);
+    let mut total_size = 0;
+    for (index, file) in files.iter().enumerate() {
+        if file.size > u64::from(u32::MAX) {
+            bail!("{} is too large for an RPM payload", file.install_path());
+        }
+        let name = format!(".{}", file.install_path());
+        total_size += write_cpio_header(&mut encoder, &name, index as u32 + 1,
+                                        u32::from(file.mode), file.mtime,
+                                        file.size as u32)?;
+        if !file.is_dir() {
+            let copied = io::copy(&mut File::open(&file.source_path)?, &mut encoder)?;
+            if copied != file.size {
+                bail!("{:?} changed while it was being archived", file.source_path);
+            }
+            total_size += copied + write_padding(&mut encoder, copied)?;
+        }
+    }
+    total_size += write_cpio_header(&mut encoder, "TRAILER!!!", 0, 0, 0, 0)?;
+    let payload = encoder.finish()?;
+    Ok((payload, total_size))
+}
+
+/// Writes the header and (padded) filename of a "newc" format cpio entry, and
+/// returns the number of bytes written.
+fn write_cpio_header<W: Write>(writer: &mut W, name: &str, inode: u32, mode: u32,
+                               mtime: u32, size: u32) -> io::Result<u64> {
+    let name_size = name.len() as u32 + 1;
+    write!(writer, "070701")?;
+    for field in &[inode, mode, 0, 0, 1, mtime, size, 0, 0, 0, 0, name_size, 0] {
+        write!(writer, "{:08x}", field)?;
+    }
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&[0])?;
+    let len = 110 + u64::from(name_size);
+    Ok(len + write_padding(writer, len)?)
+}
+
+/// Pads a cpio entry of `len` bytes to a multiple of four bytes, and returns
+/// the number of padding bytes written.
+fn write_padding<W: Write>(writer: &mut W, len: u64) -> io::Result<u64> {
+    let padding = (4 - len % 4) % 4;
+    writer.write_all(&[0; 4][..padding as usize])?;
+    Ok(padding)
+}
+
+/// Builds the main header, describing the package and its contents.
+fn generate_header(settings: &Settings, name: &str, version: &str, arch: &str,
+                   files: &[FileInfo], payload: &[u8]) -> Header {
+    let mut header = Header::new();
+    header.add(RPMTAG_HEADERI18NTABLE, HeaderValue::StringArray(vec!["C".to_string()]));
+    header.add(RPMTAG_NAME, HeaderValue::String(name.to_string()));
+    header.add(RPMTAG_VERSION, HeaderValue::String(version.to_string()));
+    header.add(RPMTAG_RELEASE, HeaderValue::String(RELEASE.to_string()));
+    let mut short_description = settings.short_description().trim();
+    if short_description.is_empty() {
+        short_description = "(none)";
+    }
+    let long_description = settings.long_description().unwrap_or("").trim();
+    let description = if long_description.is_empty() {
+        short_description
+    } else {
+        long_description
+    };
+    header.add(RPMTAG_SUMMARY, HeaderValue::I18nString(short_description.to_string()));
+    header.add(RPMTAG_DESCRIPTION, HeaderValue::I18nString(description.to_string()));
+    let build_time = settings.source_date_epoch().unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }) as u32;
+    header.add(RPMTAG_BUILDTIME, HeaderValue::Int32(vec![build_time]));
+    header.add(RPMTAG_BUILDHOST, HeaderValue::String("localhost".to_string()));
+    let total_size: u64 = files.iter().map(|file| file.size).sum();
+    header.add(RPMTAG_SIZE, HeaderValue::Int32(vec![min(total_size, u64::from(u32::MAX)) as u32]));
+    if let Some(license) = settings.license() {
+        header.add(RPMTAG_LICENSE, HeaderValue::String(license.to_string()));
+    }
+    if let Some(authors) = settings.authors_comma_separated() {
+        header.add(RPMTAG_PACKAGER, HeaderValue::String(authors));
+    }
+    header.add(RPMTAG_GROUP, HeaderValue::I18nString("Unspecified".to_string()));
+    if !settings.homepage_url().is_empty() {
+        header.add(RPMTAG_URL, HeaderValue::String(settings.homepage_url().to_string()));
+    }
+    header.add(RPMTAG_OS, HeaderValue::String("linux".to_string()));
+    header.add(RPMTAG_ARCH, HeaderValue::String(arch.to_string()));
+    // rpm treats a package without a source package name as a source
+    // package itself.
+    header.add(RPMTAG_SOURCERPM,
+               HeaderValue::String(format!("{}-{}-{}.src.rpm", name, version, RELEASE)));
+
+    // Dependencies:
+    let full_version = format!("{}-{}", version, RELEASE);
+    header.add(RPMTAG_PROVIDENAME, HeaderValue::StringArray(vec![name.to_string()]));
+    header.add(RPMTAG_PROVIDEFLAGS, HeaderValue::Int32(vec![RPMSENSE_EQUAL]));
+    header.add(RPMTAG_PROVIDEVERSION, HeaderValue::StringArray(vec![full_version]));
+    header.add(RPMTAG_REQUIRENAME, HeaderValue::StringArray(
+        RPMLIB_REQUIREMENTS.iter().map(|&(name, _)| name.to_string()).collect()));
+    header.add(RPMTAG_REQUIREFLAGS, HeaderValue::Int32(
+        vec![RPMSENSE_RPMLIB | RPMSENSE_LESS | RPMSENSE_EQUAL; RPMLIB_REQUIREMENTS.len()]));
+    header.add(RPMTAG_REQUIREVERSION, HeaderValue::StringArray(
+        RPMLIB_REQUIREMENTS.iter().map(|&(_, version)| version.to_string()).collect()));
+
+    // File list:
+    let mut dirnames = Vec::<String>::new();
+    let mut dir_indexes = Vec::new();
+    for file in files {
+        match dirnames.iter().position(|dirname| *dirname == file.dirname) {
+            Some(index) => dir_indexes.push(index as u32),
+            None => {
+                dir_indexes.push(dirnames.len() as u32);
+                dirnames.push(file.dirname.clone());
+            }
+        }
+    }
+    let num_files = files.len();
+    header.add(RPMTAG_BASENAMES, HeaderValue::StringArray(
+        files.iter().map(|file| file.basename.clone()).collect()));
+    header.add(RPMTAG_DIRNAMES, HeaderValue::StringArray(dirnames));
+    header.add(RPMTAG_DIRINDEXES, HeaderValue::Int32(dir_indexes));
+    header.add(RPMTAG_FILESIZES, HeaderValue::Int32(
+        files.iter().map(|file| file.size as u32).collect()));
+    header.add(RPMTAG_FILEMODES, HeaderValue::Int16(files.iter().map(|file| file.mode).collect()));
+    header.add(RPMTAG_FILERDEVS, HeaderValue::Int16(vec![0; num_files]));
+    header.add(RPMTAG_FILEMTIMES, HeaderValue::Int32(files.iter().map(|file| file.mtime).collect()));
+    header.add(RPMTAG_FILEDIGESTS, HeaderValue::StringArray(
+        files.iter().map(|file| file.digest.clone()).collect()));
+    header.add(RPMTAG_FILEDIGESTALGO, HeaderValue::Int32(vec![PGPHASHALGO_SHA256]));
+    header.add(RPMTAG_FILELINKTOS, HeaderValue::StringArray(vec![String::new(); num_files]));
+    header.add(RPMTAG_FILEFLAGS, HeaderValue::Int32(vec![0; num_files]));
+    header.add(RPMTAG_FILEUSERNAME, HeaderValue::StringArray(vec!["root".to_string(); num_files]));
+    header.add(RPMTAG_FILEGROUPNAME, HeaderValue::StringArray(vec!["root".to_string(); num_files]));
+    header.add(RPMTAG_FILEVERIFYFLAGS, HeaderValue::Int32(vec![0xffff_ffff; num_files]));
+    header.add(RPMTAG_FILEDEVICES, HeaderValue::Int32(vec![1; num_files]));
+    header.add(RPMTAG_FILEINODES, HeaderValue::Int32((1..num_files as u32 + 1).collect()));
+    header.add(RPMTAG_FILELANGS, HeaderValue::StringArray(vec![String::new(); num_files]));
+
+    // Payload description:
+    header.add(RPMTAG_PAYLOADFORMAT, HeaderValue::String("cpio".to_string()));
+    header.add(RPMTAG_PAYLOADCOMPRESSOR, HeaderValue::String("gzip".to_string()));
+    header.add(RPMTAG_PAYLOADFLAGS,
+               HeaderValue::String(PAYLOAD_COMPRESSION_LEVEL.to_string()));
+    header.add(RPMTAG_PAYLOADDIGEST, HeaderValue::StringArray(
+        vec![hex_string(&Sha256::digest(payload))]));
+    header.add(RPMTAG_PAYLOADDIGESTALGO, HeaderValue::Int32(vec![PGPHASHALGO_SHA256]));
+    header
+}
+
+/// Builds the signature header, which holds the sizes and digests of the
+/// serialized main header and the payload.
+fn generate_signature(header: &[u8], payload: &[u8], payload_size: u64) -> Header {
+    let mut md5 = md5::Context::new();
+    md5.consume(header);
+    md5.consume(payload);
+    let mut signature = Header::new();
+    signature.add(RPMSIGTAG_SHA256, HeaderValue::String(hex_string(&Sha256::digest(header))));
+    signature.add(RPMSIGTAG_SIZE, HeaderValue::Int32(vec![(header.len() + payload.len()) as u32]));
+    signature.add(RPMSIGTAG_MD5, HeaderValue::Binary(md5.compute().to_vec()));
+    signature.add(RPMSIGTAG_PAYLOADSIZE, HeaderValue::Int32(vec![payload_size as u32]));
+    signature
+}
+
+/// Writes the fixed-size lead that starts every RPM file.
+fn write_lead<W: Write>(writer: &mut W, full_name: &str, arch: &str) -> io::Result<()> {
+    let arch_num: u16 = match arch {
+        "i686" | "x86_64" => 1,
+        "armv7hl" => 12,
+        "aarch64" => 19,
+        _ => 0,
+    };
+    writer.write_all(&LEAD_MAGIC)?;
+    writer.write_all(&[3, 0])?; // Format version 3.0
+    writer.write_all(&0u16.to_be_bytes())?; // Binary package
+    writer.write_all(&arch_num.to_be_bytes())?;
+    let mut name = [0u8; 66];
+    let len = min(full_name.len(), name.len() - 1);
+    name[..len].copy_from_slice(&full_name.as_bytes()[..len]);
+    writer.write_all(&name)?;
+    writer.write_all(&1u16.to_be_bytes())?; // Linux
+    writer.write_all(&5u16.to_be_bytes())?; // Header-style signature
+    writer.write_all(&[0; 16])?;
+    Ok(())
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Header, HeaderValue, RPMTAG_HEADERIMMUTABLE, write_cpio_header};
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        let mut buffer = [0; 4];
+        buffer.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_be_bytes(buffer)
+    }
+
+    #[test]
+    fn header_layout() {
+        let mut header = Header::new();
+        header.add(1001, HeaderValue::String("1.0".to_string()));
+        header.add(1000, HeaderValue::String("foo".to_string()));
+        header.add(1030, HeaderValue::Int16(vec![0o100644]));
+        let bytes = header.to_bytes(RPMTAG_HEADERIMMUTABLE);
+        assert_eq!(&bytes[..4], &[0x8e, 0xad, 0xe8, 0x01]);
+        // Three entries plus the region tag.
+        assert_eq!(read_u32(&bytes, 8), 4);
+        // "foo\0" + "1.0\0" + int16 + 16-byte region trailer.
+        assert_eq!(read_u32(&bytes, 12), 4 + 4 + 2 + 16);
+        let store = 16 + 4 * 16;
+        assert_eq!(bytes.len(), store + 26);
+        // The region entry comes first and points at the trailer.
+        assert_eq!(read_u32(&bytes, 16), RPMTAG_HEADERIMMUTABLE);
+        assert_eq!(read_u32(&bytes, 24), 10);
+        // Entries are sorted by tag, with their data in the same order.
+        assert_eq!(read_u32(&bytes, 32), 1000);
+        assert_eq!(read_u32(&bytes, 40), 0);
+        assert_eq!(read_u32(&bytes, 48), 1001);
+        assert_eq!(read_u32(&bytes, 56), 4);
+        assert_eq!(read_u32(&bytes, 64), 1030);
+        assert_eq!(read_u32(&bytes, 72), 8);
+        assert_eq!(&bytes[store..store + 8], b"foo\x001.0\x00");
+        // The trailer's offset covers all four index entries.
+        assert_eq!(read_u32(&bytes, store + 10), RPMTAG_HEADERIMMUTABLE);
+        assert_eq!(read_u32(&bytes, store + 18) as i32, -64);
+    }
+
+    #[test]
+    fn cpio_header_is_padded() {
+        let mut bytes = Vec::new();
+        let len = write_cpio_header(&mut bytes, "./usr/bin/foo", 1, 0o100755, 0, 3).unwrap();
+        assert_eq!(len as usize, bytes.len());
+        assert_eq!(bytes.len() % 4, 0);
+        assert!(bytes.starts_with(b"07070100000001000081ed"));
+        assert_eq!(&bytes[110..124], b"./usr/bin/foo\x00");
+    }
 }
@@ -1,11 +1,13 @@
 use clap::ArgMatches;
 use glob;
 use std;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use super::category::AppCategory;
+use super::common;
+use super::version::Version;
 use target_build_utils::TargetInfo;
 use toml;
 use walkdir;
@@ -17,12 +19,16 @@ pub enum PackageType {
     WindowsMsi,
     Deb,
     Rpm,
+    AppImage,
+    Archive,
 }
 
 impl PackageType {
     pub fn from_short_name(name: &str) -> Option<PackageType> {
         // Other types we may eventually want to support: apk
         match name {
+            "appimage" => Some(PackageType::AppImage),
+            "archive" => Some(PackageType::Archive),
             "deb" => Some(PackageType::Deb),
             "ios" => Some(PackageType::IosBundle),
             "msi" => Some(PackageType::WindowsMsi),
@@ -39,6 +45,8 @@ impl PackageType {
             PackageType::WindowsMsi => "msi",
             PackageType::OsxBundle => "osx",
             PackageType::Rpm => "rpm",
+            PackageType::AppImage => "appimage",
+            PackageType::Archive => "archive",
         }
     }
 
@@ -51,8 +59,89 @@ const ALL_PACKAGE_TYPES: &[PackageType] = &[
     PackageType::WindowsMsi,
     PackageType::OsxBundle,
     PackageType::Rpm,
+    PackageType::AppImage,
+    PackageType::Archive,
 ];
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DebCompression {
+    Gzip,
+    Xz,
+    Zstd,
+    None,
+}
+
+impl DebCompression {
+    pub fn from_short_name(name: &str) -> Option<DebCompression> {
+        match name {
+            "gzip" => Some(DebCompression::Gzip),
+            "xz" => Some(DebCompression::Xz),
+            "zstd" => Some(DebCompression::Zstd),
+            "none" => Some(DebCompression::None),
+            _ => None,
+        }
+    }
+
+    pub fn short_name(&self) -> &'static str {
+        match *self {
+            DebCompression::Gzip => "gzip",
+            DebCompression::Xz => "xz",
+            DebCompression::Zstd => "zstd",
+            DebCompression::None => "none",
+        }
+    }
+
+    /// Returns the file extension for a tar archive compressed this way.
+    pub fn tar_extension(&self) -> &'static str {
+        match *self {
+            DebCompression::Gzip => "tar.gz",
+            DebCompression::Xz => "tar.xz",
+            DebCompression::Zstd => "tar.zst",
+            DebCompression::None => "tar",
+        }
+    }
+
+    /// Returns the range of supported compression levels, and the level used
+    /// by default.
+    pub fn levels(&self) -> (u32, u32, u32) {
+        match *self {
+            DebCompression::Gzip => (0, 9, 9),
+            DebCompression::Xz => (0, 9, 6),
+            DebCompression::Zstd => (1, 19, 3),
+            DebCompression::None => (0, 0, 0),
+        }
+    }
+
+    pub fn all() -> &'static [DebCompression] { ALL_DEB_COMPRESSIONS }
+}
+
+const ALL_DEB_COMPRESSIONS: &[DebCompression] = &[
+    DebCompression::Gzip,
+    DebCompression::Xz,
+    DebCompression::Zstd,
+    DebCompression::None,
+];
+
+/// Whether an MSI package installs the app for the current user only, for
+/// all users of the machine, or lets the user choose.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MsiInstallScope {
+    User,
+    Machine,
+    Both,
+}
+
+impl MsiInstallScope {
+    pub fn from_short_name(name: &str) -> Option<MsiInstallScope> {
+        match name {
+            "user" => Some(MsiInstallScope::User),
+            "machine" => Some(MsiInstallScope::Machine),
+            "both" => Some(MsiInstallScope::Both),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum BuildArtifact {
     Main,
@@ -60,6 +149,68 @@ pub enum BuildArtifact {
     Example(String),
 }
 
+/// An entry in a `resources` list: either a path or glob, whose matching
+/// files are placed according to their relative paths, or a table that maps
+/// the files matching the `src` path or glob into the `dest` directory.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum ResourceSpec {
+    Path(String),
+    Mapping { src: String, dest: String },
+}
+
+/// A kind of document that the app can open, identified by its file
+/// extensions (without the leading dot) and optionally its MIME type.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct FileAssociation {
+    pub extensions: Vec<String>,
+    pub mime_type: Option<String>,
+    /// A human-readable name for this kind of document.
+    pub description: Option<String>,
+}
+
+impl FileAssociation {
+    /// Returns the human-readable name for this kind of document, falling
+    /// back to one made from its first extension (e.g. "FOO document").
+    pub fn display_name(&self) -> String {
+        match self.description {
+            Some(ref description) => description.clone(),
+            None => {
+                let extension = self.extensions.first().map(String::as_str).unwrap_or("");
+                format!("{} document", extension.to_uppercase())
+            }
+        }
+    }
+}
+
+/// An optional feature of an MSI package, which users can choose whether to
+/// install, along with the resource files that are part of it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MsiFeature {
+    /// The feature's identifier within the package.
+    pub name: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The feature's install level; it is installed by default if this is 1
+    /// (the default), and not otherwise.
+    pub level: Option<i32>,
+    resources: Vec<ResourceSpec>,
+}
+
+/// A registry value that an MSI package writes on installation (and removes
+/// again on uninstallation).
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct MsiRegistryEntry {
+    /// The registry root: `HKCR`, `HKCU`, `HKLM` or `HKU`.
+    pub root: String,
+    pub key: String,
+    /// The value name; if `None`, the key's default value is written.
+    pub name: Option<String>,
+    /// The value, in MSI formatted-string syntax (e.g. `[INSTALLDIR]`, or
+    /// `#1` for a DWORD).
+    pub value: String,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 struct BundleSettings {
     // General settings:
@@ -67,20 +218,58 @@ struct BundleSettings {
     identifier: Option<String>,
     icon: Option<Vec<String>>,
     version: Option<String>,
-    resources: Option<Vec<String>>,
+    resources: Option<Vec<ResourceSpec>>,
     copyright: Option<String>,
     category: Option<AppCategory>,
     short_description: Option<String>,
     long_description: Option<String>,
     script: Option<PathBuf>,
+    license_file: Option<PathBuf>,
+    file_associations: Option<Vec<FileAssociation>>,
+    url_schemes: Option<Vec<String>>,
     // OS-specific settings:
     linux_mime_types: Option<Vec<String>>,
+    linux_resources: Option<Vec<ResourceSpec>>,
     linux_exec_args: Option<String>,
     linux_use_terminal: Option<bool>,
     deb_depends: Option<Vec<String>>,
+    deb_pre_depends: Option<Vec<String>>,
+    deb_recommends: Option<Vec<String>>,
+    deb_suggests: Option<Vec<String>>,
+    deb_conflicts: Option<Vec<String>>,
+    deb_breaks: Option<Vec<String>>,
+    deb_provides: Option<Vec<String>>,
+    deb_replaces: Option<Vec<String>>,
+    deb_section: Option<String>,
+    deb_priority: Option<String>,
+    deb_essential: Option<bool>,
+    deb_maintainer: Option<String>,
+    deb_conffiles: Option<BTreeMap<String, PathBuf>>,
+    deb_compression: Option<String>,
+    deb_compression_level: Option<u32>,
+    deb_auto_depends: Option<bool>,
+    deb_soname_packages: Option<HashMap<String, String>>,
+    deb_preinst: Option<PathBuf>,
+    deb_postinst: Option<PathBuf>,
+    deb_prerm: Option<PathBuf>,
+    deb_postrm: Option<PathBuf>,
+    appimage_runtime: Option<PathBuf>,
+    archive_compression: Option<String>,
+    ios_resources: Option<Vec<ResourceSpec>>,
+    msi_resources: Option<Vec<ResourceSpec>>,
+    msi_upgrade_code: Option<String>,
+    msi_install_scope: Option<String>,
+    msi_registry: Option<Vec<MsiRegistryEntry>>,
+    msi_environment: Option<BTreeMap<String, String>>,
+    msi_add_to_path: Option<bool>,
+    msi_start_menu_shortcut: Option<bool>,
+    msi_desktop_shortcut: Option<bool>,
+    msi_ui: Option<bool>,
+    msi_features: Option<Vec<MsiFeature>>,
     osx_frameworks: Option<Vec<String>>,
     osx_minimum_system_version: Option<String>,
     osx_url_schemes: Option<Vec<String>>,
+    osx_resources: Option<Vec<ResourceSpec>>,
     // Bundles for other binaries/examples:
     bin: Option<HashMap<String, BundleSettings>>,
     example: Option<HashMap<String, BundleSettings>>,
@@ -97,6 +286,7 @@ struct PackageSettings {
     version: String,
     description: String,
     homepage: Option<String>,
+    license: Option<String>,
     authors: Option<Vec<String>>,
     metadata: Option<MetadataSettings>,
 }
@@ -125,6 +315,11 @@ pub struct Settings {
     no_default_features: bool,
     binary_path: PathBuf,
     binary_name: String,
+    source_date_epoch: Option<u64>, // If `Some`, build reproducibly using this timestamp
+    deb_compression: DebCompression,
+    deb_compression_level: u32,
+    archive_compression: DebCompression,
+    msi_install_scope: MsiInstallScope,
     bundle_settings: BundleSettings,
 }
 
@@ -177,6 +372,11 @@ impl Settings {
             Some(features) => Some(features.into()),
             None => None
         };
+        let source_date_epoch = match common::source_date_epoch()? {
+            Some(epoch) => Some(epoch),
+            None if matches.is_present("reproducible") => Some(0),
+            None => None,
+        };
         let cargo_settings = CargoSettings::load(&current_dir)?;
         let package = match cargo_settings.package {
             Some(package_info) => package_info,
@@ -205,6 +405,41 @@ impl Settings {
             }
         };
         let binary_path = target_dir.join(&binary_name);
+        let deb_compression_name = matches.value_of("deb-compression")
+            .or(bundle_settings.deb_compression.as_ref().map(String::as_str));
+        let deb_compression = match deb_compression_name {
+            Some(name) => match DebCompression::from_short_name(name) {
+                Some(compression) => compression,
+                None => bail!("Unsupported deb compression: {}", name),
+            },
+            None => DebCompression::Gzip,
+        };
+        let (min_level, max_level, default_level) = deb_compression.levels();
+        let deb_compression_level = match matches.value_of("deb-compression-level") {
+            Some(level) => match level.parse::<u32>() {
+                Ok(level) => level,
+                Err(_) => bail!("Invalid deb compression level: {}", level),
+            },
+            None => bundle_settings.deb_compression_level.unwrap_or(default_level),
+        };
+        if deb_compression_level < min_level || deb_compression_level > max_level {
+            bail!("Compression level for {} must be between {} and {}, not {}",
+                  deb_compression.short_name(), min_level, max_level, deb_compression_level);
+        }
+        let archive_compression = match bundle_settings.archive_compression {
+            Some(ref name) => match DebCompression::from_short_name(name) {
+                Some(compression) => compression,
+                None => bail!("Unsupported archive_compression: {}", name),
+            },
+            None => DebCompression::Gzip,
+        };
+        let msi_install_scope = match bundle_settings.msi_install_scope {
+            Some(ref name) => match MsiInstallScope::from_short_name(name) {
+                Some(scope) => scope,
+                None => bail!("Unsupported msi_install_scope: {}", name),
+            },
+            None => MsiInstallScope::Machine,
+        };
         Ok(Settings {
             package,
             package_type,
@@ -217,6 +452,11 @@ impl Settings {
             project_out_directory: target_dir,
             binary_path,
             binary_name,
+            source_date_epoch,
+            deb_compression,
+            deb_compression_level,
+            archive_compression,
+            msi_install_scope,
             bundle_settings,
         })
     }
@@ -283,6 +523,16 @@ impl Settings {
         }
     }
 
+    /// Returns the operating system that the binary being bundled is for
+    /// (e.g. "linux" or "macos" or "windows").
+    pub fn binary_os(&self) -> &str {
+        if let Some((_, ref info)) = self.target {
+            info.target_os()
+        } else {
+            std::env::consts::OS
+        }
+    }
+
     /// Returns the file name of the binary being bundled.
     pub fn binary_name(&self) -> &str { &self.binary_name }
 
@@ -300,15 +550,10 @@ impl Settings {
         if let Some(package_type) = self.package_type {
             Ok(vec![package_type])
         } else {
-            let target_os = if let Some((_, ref info)) = self.target {
-                info.target_os()
-            } else {
-                std::env::consts::OS
-            };
-            match target_os {
+            match self.binary_os() {
                 "macos" => Ok(vec![PackageType::OsxBundle]),
                 "ios" => Ok(vec![PackageType::IosBundle]),
-                "linux" => Ok(vec![PackageType::Deb]), // TODO: Do Rpm too, once it's implemented.
+                "linux" => Ok(vec![PackageType::Deb, PackageType::Rpm]),
                 "windows" => Ok(vec![PackageType::WindowsMsi]),
                 os => bail!("Native {} bundles not yet supported.", os),
             }
@@ -343,6 +588,12 @@ impl Settings {
 
     pub fn no_default_features(&self) -> bool { self.no_default_features }
 
+    /// If the bundle is being built reproducibly (because `--reproducible`
+    /// was passed, or `SOURCE_DATE_EPOCH` is set), returns the timestamp that
+    /// should be used in place of the current time, and that file
+    /// modification times should be clamped to.  Otherwise, returns `None`.
+    pub fn source_date_epoch(&self) -> Option<u64> { self.source_date_epoch }
+
     pub fn bundle_name(&self) -> &str {
         self.bundle_settings.name.as_ref().unwrap_or(&self.package.name)
     }
@@ -359,19 +610,47 @@ impl Settings {
         }
     }
 
-    /// Returns an iterator over the resource files to be included in this
-    /// bundle.
-    pub fn resource_files(&self) -> ResourcePaths {
-        match self.bundle_settings.resources {
-            Some(ref paths) => ResourcePaths::new(paths.as_slice(), true),
-            None => ResourcePaths::new(&[], true),
-        }
+    /// Returns the resource files to be included in a bundle of the given
+    /// type, from both the `resources` list and the format's own list.  Each
+    /// file is returned along with the relative path, within the bundle's
+    /// resources directory, where it should be placed.
+    pub fn resource_files(&self, package_type: PackageType) -> ::Result<Vec<(PathBuf, PathBuf)>> {
+        let format_resources = match package_type {
+            PackageType::OsxBundle => &self.bundle_settings.osx_resources,
+            PackageType::IosBundle => &self.bundle_settings.ios_resources,
+            PackageType::WindowsMsi => &self.bundle_settings.msi_resources,
+            PackageType::Deb | PackageType::Rpm | PackageType::AppImage => {
+                &self.bundle_settings.linux_resources
+            }
+            // An archive gets the resources of its target's native bundles.
+            PackageType::Archive => match self.binary_os() {
+                "linux" => &self.bundle_settings.linux_resources,
+                "macos" => &self.bundle_settings.osx_resources,
+                "windows" => &self.bundle_settings.msi_resources,
+                _ => &None,
+            },
+        };
+        let specs = self.bundle_settings.resources.iter().chain(format_resources.iter()).flatten();
+        resolve_resource_specs(specs)
+    }
+
+    /// Returns the resource files that are part of an optional MSI feature,
+    /// in the same form as `resource_files`.
+    pub fn msi_feature_resource_files(&self, feature: &MsiFeature)
+                                      -> ::Result<Vec<(PathBuf, PathBuf)>> {
+        resolve_resource_specs(feature.resources.iter())
     }
 
     pub fn version_string(&self) -> &str {
         self.bundle_settings.version.as_ref().unwrap_or(&self.package.version)
     }
 
+    /// Returns the parsed app version, for the package formats that translate
+    /// it into their own version format.  This fails if the version isn't a
+    /// semver version; formats that just use the version in file names should
+    /// use `version_string()` instead.
+    pub fn version(&self) -> ::Result<Version> { Version::parse(self.version_string()) }
+
     pub fn copyright_string(&self) -> Option<&str> {
         self.bundle_settings.copyright.as_ref().map(String::as_str)
     }
@@ -396,6 +675,10 @@ impl Settings {
         &self.package.homepage.as_ref().map(String::as_str).unwrap_or("")
     }
 
+    pub fn license(&self) -> Option<&str> {
+        self.package.license.as_ref().map(String::as_str)
+    }
+
     pub fn app_category(&self) -> Option<AppCategory> {
         self.bundle_settings.category
     }
@@ -409,6 +692,12 @@ impl Settings {
         self.bundle_settings.long_description.as_ref().map(String::as_str)
     }
 
+    /// Returns the path to the app's license agreement (in plain text or
+    /// RTF), if any.
+    pub fn license_file(&self) -> Option<&Path> {
+        self.bundle_settings.license_file.as_ref().map(PathBuf::as_path)
+    }
+
     pub fn debian_dependencies(&self) -> &[String] {
         match self.bundle_settings.deb_depends {
             Some(ref dependencies) => dependencies.as_slice(),
@@ -416,6 +705,156 @@ impl Settings {
         }
     }
 
+    /// Returns the packages that must be fully installed before this package is
+    /// unpacked (the `Pre-Depends:` field).
+    pub fn debian_pre_dependencies(&self) -> &[String] {
+        match self.bundle_settings.deb_pre_depends {
+            Some(ref packages) => packages.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the packages that are recommended alongside this package (the
+    /// `Recommends:` field).
+    pub fn debian_recommends(&self) -> &[String] {
+        match self.bundle_settings.deb_recommends {
+            Some(ref packages) => packages.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the packages that may be useful alongside this package (the
+    /// `Suggests:` field).
+    pub fn debian_suggests(&self) -> &[String] {
+        match self.bundle_settings.deb_suggests {
+            Some(ref packages) => packages.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the packages that cannot be installed alongside this package (the
+    /// `Conflicts:` field).
+    pub fn debian_conflicts(&self) -> &[String] {
+        match self.bundle_settings.deb_conflicts {
+            Some(ref packages) => packages.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the packages that are broken by this package (the `Breaks:`
+    /// field).
+    pub fn debian_breaks(&self) -> &[String] {
+        match self.bundle_settings.deb_breaks {
+            Some(ref packages) => packages.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the (virtual) packages that this package provides (the `Provides:`
+    /// field).
+    pub fn debian_provides(&self) -> &[String] {
+        match self.bundle_settings.deb_provides {
+            Some(ref packages) => packages.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the packages that this package replaces files from (the
+    /// `Replaces:` field).
+    pub fn debian_replaces(&self) -> &[String] {
+        match self.bundle_settings.deb_replaces {
+            Some(ref packages) => packages.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the archive section of the deb package, which defaults to the
+    /// closest match for the app category (if any).
+    pub fn debian_section(&self) -> Option<&str> {
+        match self.bundle_settings.deb_section {
+            Some(ref section) => Some(section.as_str()),
+            None => self.app_category().map(|category| category.debian_section()),
+        }
+    }
+
+    /// Returns the priority of the deb package (`optional` by default).
+    pub fn debian_priority(&self) -> &str {
+        self.bundle_settings.deb_priority.as_ref().map(String::as_str).unwrap_or("optional")
+    }
+
+    /// Returns true if the deb package should be marked as essential, meaning
+    /// that the package manager will refuse to remove it.
+    pub fn debian_essential(&self) -> bool {
+        self.bundle_settings.deb_essential.unwrap_or(false)
+    }
+
+    /// Returns the `Name <email>` of the deb package's maintainer, if it was
+    /// given explicitly.
+    pub fn debian_maintainer(&self) -> Option<&str> {
+        self.bundle_settings.deb_maintainer.as_ref().map(String::as_str)
+    }
+
+    /// Returns the configuration files to install with the deb package, as a
+    /// map from install paths (under `/etc/`) to source paths, if any.
+    pub fn debian_conffiles(&self) -> Option<&BTreeMap<String, PathBuf>> {
+        self.bundle_settings.deb_conffiles.as_ref()
+    }
+
+    /// Returns how the members of the deb package should be compressed.
+    pub fn debian_compression(&self) -> DebCompression { self.deb_compression }
+
+    /// Returns the compression level for the members of the deb package.
+    pub fn debian_compression_level(&self) -> u32 { self.deb_compression_level }
+
+    /// Returns how to compress tar archives built with the `archive` format.
+    pub fn archive_compression(&self) -> DebCompression { self.archive_compression }
+
+    /// Returns true if the shared libraries that the binary links against
+    /// should be detected, and the packages providing them added to the deb
+    /// package's dependencies.
+    pub fn debian_auto_dependencies(&self) -> bool {
+        self.bundle_settings.deb_auto_depends.unwrap_or(false)
+    }
+
+    /// Returns the package that provides the shared library with the given
+    /// soname, according to the `deb_soname_packages` table, if it is listed.
+    pub fn debian_soname_package(&self, soname: &str) -> Option<&str> {
+        match self.bundle_settings.deb_soname_packages {
+            Some(ref packages) => packages.get(soname).map(String::as_str),
+            None => None,
+        }
+    }
+
+    /// Returns the path to the script to be run before the deb package is
+    /// unpacked, if any.
+    pub fn debian_preinst_script(&self) -> Option<&Path> {
+        self.bundle_settings.deb_preinst.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Returns the path to the script to be run after the deb package is
+    /// unpacked, if any.
+    pub fn debian_postinst_script(&self) -> Option<&Path> {
+        self.bundle_settings.deb_postinst.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Returns the path to the script to be run before the deb package is
+    /// removed, if any.
+    pub fn debian_prerm_script(&self) -> Option<&Path> {
+        self.bundle_settings.deb_prerm.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Returns the path to the script to be run after the deb package is
+    /// removed, if any.
+    pub fn debian_postrm_script(&self) -> Option<&Path> {
+        self.bundle_settings.deb_postrm.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Returns the path to the AppImage runtime executable that is prepended
+    /// to the SquashFS image of an AppImage, if one was given.
+    pub fn appimage_runtime(&self) -> Option<&Path> {
+        self.bundle_settings.appimage_runtime.as_ref().map(PathBuf::as_path)
+    }
+
     pub fn linux_mime_types(&self) -> &[String] {
         match self.bundle_settings.linux_mime_types {
             Some(ref mime_types) => mime_types.as_slice(),
@@ -423,6 +862,23 @@ impl Settings {
         }
     }
 
+    /// Returns the kinds of documents that the app can open, on all
+    /// platforms.
+    pub fn file_associations(&self) -> &[FileAssociation] {
+        match self.bundle_settings.file_associations {
+            Some(ref associations) => associations.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the URL schemes that the app handles, on all platforms.
+    pub fn url_schemes(&self) -> &[String] {
+        match self.bundle_settings.url_schemes {
+            Some(ref schemes) => schemes.as_slice(),
+            None => &[],
+        }
+    }
+
     pub fn linux_use_terminal(&self) -> Option<bool> {
         self.bundle_settings.linux_use_terminal
     }
@@ -431,6 +887,58 @@ impl Settings {
         self.bundle_settings.linux_exec_args.as_ref().map(String::as_str)
     }
 
+    /// Returns the UpgradeCode GUID to use for MSI packages, if it was given
+    /// explicitly (e.g. to stay compatible with installers built by WiX).
+    pub fn msi_upgrade_code(&self) -> Option<&str> {
+        self.bundle_settings.msi_upgrade_code.as_ref().map(String::as_str)
+    }
+
+    /// Returns who MSI packages install the app for (per-machine by default).
+    pub fn msi_install_scope(&self) -> MsiInstallScope { self.msi_install_scope }
+
+    pub fn msi_registry(&self) -> &[MsiRegistryEntry] {
+        match self.bundle_settings.msi_registry {
+            Some(ref entries) => entries.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns the environment variables that MSI packages should set, as a
+    /// map from name to value.
+    pub fn msi_environment(&self) -> Option<&BTreeMap<String, String>> {
+        self.bundle_settings.msi_environment.as_ref()
+    }
+
+    /// Returns true if MSI packages should append the install dir to PATH.
+    pub fn msi_add_to_path(&self) -> bool {
+        self.bundle_settings.msi_add_to_path.unwrap_or(false)
+    }
+
+    /// Returns true if MSI packages should create a Start menu shortcut for
+    /// the app (the default).
+    pub fn msi_start_menu_shortcut(&self) -> bool {
+        self.bundle_settings.msi_start_menu_shortcut.unwrap_or(true)
+    }
+
+    /// Returns true if MSI packages should create a desktop shortcut for the
+    /// app.
+    pub fn msi_desktop_shortcut(&self) -> bool {
+        self.bundle_settings.msi_desktop_shortcut.unwrap_or(false)
+    }
+
+    pub fn msi_features(&self) -> &[MsiFeature] {
+        match self.bundle_settings.msi_features {
+            Some(ref features) => features.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Returns true if MSI packages should have an installer UI, which by
+    /// default they do only if there is a license agreement to show.
+    pub fn msi_ui(&self) -> bool {
+        self.bundle_settings.msi_ui.unwrap_or(self.bundle_settings.license_file.is_some())
+    }
+
     pub fn osx_frameworks(&self) -> &[String] {
         match self.bundle_settings.osx_frameworks {
             Some(ref frameworks) => frameworks.as_slice(),
@@ -461,6 +969,75 @@ fn bundle_settings_from_table(opt_map: &Option<HashMap<String, BundleSettings>>,
     }
 }
 
+/// Resolves a list of resource specs into the resource files that they
+/// match, along with the relative path where each should be placed.
+fn resolve_resource_specs<'a, I>(specs: I) -> ::Result<Vec<(PathBuf, PathBuf)>>
+    where I: Iterator<Item = &'a ResourceSpec>
+{
+    let mut files = Vec::new();
+    let mut sources_by_dest = HashMap::new();
+    for spec in specs {
+        let (pattern, dest) = match *spec {
+            ResourceSpec::Path(ref pattern) => (pattern, None),
+            ResourceSpec::Mapping { ref src, ref dest } => (src, Some(dest.as_str())),
+        };
+        // A trailing `**` only matches directories, so match everything
+        // beneath them instead (directories are then walked as usual).
+        let glob_pattern = if pattern.ends_with("**") {
+            format!("{}/*", pattern)
+        } else {
+            pattern.clone()
+        };
+        for src in ResourcePaths::new(std::slice::from_ref(&glob_pattern), true) {
+            let src = src?;
+            let dest_path = match dest {
+                Some(dest) => mapped_resource_path(pattern, dest, &src)?,
+                None => common::resource_relpath(&src),
+            };
+            // Overlapping patterns may match the same file more than once.
+            match sources_by_dest.get(&dest_path) {
+                Some(existing) if *existing == src => continue,
+                Some(_) => bail!("More than one resource file would be placed at {:?}",
+                                 dest_path),
+                None => {}
+            }
+            sources_by_dest.insert(dest_path.clone(), src.clone());
+            files.push((src, dest_path));
+        }
+    }
+    Ok(files)
+}
+
+/// Returns the relative path where the resource file at `src`, which matched
+/// the `pattern` of a `{ src = pattern, dest = dest }` resource mapping,
+/// should be placed.  The part of `src` below the pattern's non-glob prefix is
+/// placed under `dest`.  When the pattern names a single file, the file keeps
+/// its name within `dest` if `dest` ends with a slash, and is renamed to
+/// `dest` otherwise.
+fn mapped_resource_path(pattern: &str, dest: &str, src: &Path) -> ::Result<PathBuf> {
+    let dest_path = Path::new(dest);
+    if !dest_path.as_os_str().is_empty() && !common::is_plain_relative_path(dest_path) {
+        bail!("Resource destination {:?} must be a relative path without `.` or `..`", dest);
+    }
+    let base: PathBuf = Path::new(pattern).components()
+        .take_while(|component| {
+            !component.as_os_str().to_string_lossy().contains(['*', '?', '['])
+        })
+        .collect();
+    if src == base.as_path() {
+        if dest.is_empty() || dest.ends_with('/') {
+            Ok(dest_path.join(src.file_name().unwrap()))
+        } else {
+            Ok(dest_path.to_path_buf())
+        }
+    } else {
+        match src.strip_prefix(&base) {
+            Ok(rel_path) => Ok(dest_path.join(rel_path)),
+            Err(_) => bail!("Resource file {:?} is not within {:?}", src, base),
+        }
+    }
+}
+
 pub struct ResourcePaths<'a> {
     pattern_iter: std::slice::Iter<'a, String>,
     glob_iter: Option<glob::Paths>,
@@ -506,7 +1083,8 @@ impl<'a> Iterator for ResourcePaths<'a> {
                     };
                     if path.is_dir() {
                         if self.allow_walk {
-                            let walk = walkdir::WalkDir::new(path);
+                            let walk = walkdir::WalkDir::new(path)
+                                .sort_by(|a, b| a.file_name().cmp(b.file_name()));
                             self.walk_iter = Some(walk.into_iter());
                             continue;
                         } else {
@@ -533,7 +1111,9 @@ impl<'a> Iterator for ResourcePaths<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::{AppCategory, BundleSettings, CargoSettings};
+    use super::{AppCategory, BundleSettings, CargoSettings, MsiRegistryEntry, ResourceSpec,
+                mapped_resource_path};
+    use std::path::{Path, PathBuf};
     use toml;
 
     #[test]
@@ -550,8 +1130,12 @@ mod tests {
             [package.metadata.bundle]\n\
             name = \"Example Application\"\n\
             identifier = \"com.example.app\"\n\
-            resources = [\"data\", \"foo/bar\"]\n\
+            resources = [\"data\", \"foo/bar\", { src = \"assets/**\", dest = \"data/\" }]\n\
             category = \"Puzzle Game\"\n\
+            deb_postinst = \"scripts/postinst.sh\"\n\
+            deb_replaces = [\"example-legacy\"]\n\
+            deb_section = \"games\"\n\
+            msi_registry = [{ root = \"HKCU\", key = \"Software\\\\Example\", value = \"#1\" }]\n\
             long_description = \"\"\"\n\
             This is an example of a\n\
             simple application.\n\
@@ -575,8 +1159,25 @@ mod tests {
         assert_eq!(bundle.icon, None);
         assert_eq!(bundle.version, None);
         assert_eq!(bundle.resources,
-                   Some(vec!["data".to_string(), "foo/bar".to_string()]));
+                   Some(vec![ResourceSpec::Path("data".to_string()),
+                             ResourceSpec::Path("foo/bar".to_string()),
+                             ResourceSpec::Mapping {
+                                 src: "assets/**".to_string(),
+                                 dest: "data/".to_string(),
+                             }]));
         assert_eq!(bundle.category, Some(AppCategory::PuzzleGame));
+        assert_eq!(bundle.deb_preinst, None);
+        assert_eq!(bundle.deb_postinst, Some(PathBuf::from("scripts/postinst.sh")));
+        assert_eq!(bundle.deb_replaces, Some(vec!["example-legacy".to_string()]));
+        assert_eq!(bundle.deb_section, Some("games".to_string()));
+        assert_eq!(bundle.deb_essential, None);
+        assert_eq!(bundle.msi_registry,
+                   Some(vec![MsiRegistryEntry {
+                       root: "HKCU".to_string(),
+                       key: "Software\\Example".to_string(),
+                       name: None,
+                       value: "#1".to_string(),
+                   }]));
         assert_eq!(bundle.long_description,
                    Some("This is an example of a\n\
                          simple application.\n".to_string()));
@@ -629,4 +1230,18 @@ mod tests {
         let baz: &BundleSettings = examples.get("baz").unwrap();
         assert_eq!(baz.name, Some("Baz Example".to_string()));
     }
+
+    #[test]
+    fn mapped_resource_paths() {
+        let map = |pattern, dest, src| mapped_resource_path(pattern, dest, Path::new(src)).unwrap();
+        assert_eq!(map("assets/**", "data/", "assets/img/a.png"), PathBuf::from("data/img/a.png"));
+        assert_eq!(map("assets/*.png", "data", "assets/a.png"), PathBuf::from("data/a.png"));
+        assert_eq!(map("../shared", "", "../shared/b.txt"), PathBuf::from("b.txt"));
+        assert_eq!(map("config/app.toml", "etc/", "config/app.toml"),
+                   PathBuf::from("etc/app.toml"));
+        assert_eq!(map("config/app.toml", "etc/default.toml", "config/app.toml"),
+                   PathBuf::from("etc/default.toml"));
+        assert!(mapped_resource_path("assets/**", "../data", Path::new("assets/a.png")).is_err());
+        assert!(mapped_resource_path("assets/**", "/data", Path::new("assets/a.png")).is_err());
+    }
 }
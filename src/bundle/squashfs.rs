@@ -0,0 +1,602 @@
+// A minimal writer for SquashFS 4.0 filesystem images, which AppImages use
+// to hold their files.
+//
+// A SquashFS image starts with a superblock, which is followed by the data
+// blocks of each file, and then by a series of tables.  The tables are made
+// up of "metadata blocks" of up to 8 KiB each, which are prefixed with a
+// 16-bit length whose high bit is set if the block is stored uncompressed:
+//
+// * the inode table, with an inode for each file and directory;
+// * the directory table, with the list of entries for each directory;
+// * the ID table, with the user and group IDs that the inodes refer to.
+//
+// Things that refer to an inode or directory listing do so by the position
+// of its metadata block within its table, and its offset within that block
+// once uncompressed.  We write the simplest version of each structure: files
+// are stored as whole blocks (with no tail-end "fragments" packed together),
+// everything is owned by root, and there are no extended attributes or NFS
+// export table.  Blocks are zlib-compressed whenever that makes them smaller.
+//
+// For more information about the format, see
+// https://dr-emann.github.io/squashfs/
+
+use super::common;
+use flate2;
+use flate2::write::ZlibEncoder;
+use std::cmp::min;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAGIC: u32 = 0x7371_7368;
+const VERSION_MAJOR: u16 = 4;
+const VERSION_MINOR: u16 = 0;
+const SUPERBLOCK_SIZE: u64 = 96;
+
+// The size of each data block:
+const BLOCK_LOG: u16 = 17;
+const BLOCK_SIZE: u32 = 1 << BLOCK_LOG;
+// The size of each metadata block, before compression:
+const METADATA_BLOCK_SIZE: usize = 8192;
+
+const COMPRESSION_ZLIB: u16 = 1;
+const FLAG_NO_FRAGMENTS: u16 = 0x10;
+const FLAG_NO_XATTRS: u16 = 0x200;
+// Bits that mark metadata and data blocks as stored uncompressed:
+const METADATA_UNCOMPRESSED: u16 = 0x8000;
+const DATA_UNCOMPRESSED: u32 = 1 << 24;
+// The table position that indicates that a table is absent:
+const NO_TABLE: u64 = 0xffff_ffff_ffff_ffff;
+// The fragment index that indicates that a file has no fragment:
+const NO_FRAGMENT: u32 = 0xffff_ffff;
+
+const INODE_TYPE_DIRECTORY: u16 = 1;
+const INODE_TYPE_FILE: u16 = 2;
+
+// The maximum number of entries that one directory header can cover:
+const DIRECTORY_HEADER_MAX_ENTRIES: usize = 256;
+
+/// Writes a SquashFS image of the contents of `dir` to `writer`, starting at
+/// its current position (so that the image can follow other data, as in an
+/// AppImage).  Every file and directory is owned by root; directories get
+/// 0755 permissions, as do files for which `is_executable` (which is given
+/// each file's path relative to `dir`) returns true, and other files get
+/// 0644.  Modification times are clamped to the `source_date_epoch`, if any.
+pub fn write_squashfs<W, F>(dir: &Path, writer: &mut W, is_executable: F,
+                            source_date_epoch: Option<u64>)
+                            -> ::Result<()>
+    where W: Write + Seek,
+          F: Fn(&Path, &fs::Metadata) -> bool
+{
+    let mut next_inode_number = 1;
+    let mut root = read_node(dir, PathBuf::new(), &is_executable, source_date_epoch)?;
+    root.inode_number = next_inode_number;
+    next_inode_number += 1;
+    assign_inode_numbers(&mut root, &mut next_inode_number);
+    let inode_count = next_inode_number - 1;
+
+    let base = writer.stream_position()?;
+    writer.write_all(&[0; SUPERBLOCK_SIZE as usize])?;
+    let mut image = ImageWriter {
+        writer,
+        position: SUPERBLOCK_SIZE,
+        inodes: MetadataWriter::new(),
+        directories: MetadataWriter::new(),
+    };
+    // By convention, the root directory's parent is one past the last inode.
+    let root_inode = image.write_node(dir, &root, inode_count + 1)?.to_inode_ref();
+
+    let inode_table_start = image.position;
+    let inodes = mem::replace(&mut image.inodes, MetadataWriter::new()).finish()?;
+    image.write(&inodes)?;
+    let directory_table_start = image.position;
+    let directories = mem::replace(&mut image.directories, MetadataWriter::new()).finish()?;
+    image.write(&directories)?;
+    // The ID table consists of metadata blocks listing the IDs, followed by
+    // the positions of those blocks.  We only need root's ID (zero).
+    let mut ids = MetadataWriter::new();
+    ids.write(&le32(0))?;
+    let ids = ids.finish()?;
+    let id_block_start = image.position;
+    image.write(&ids)?;
+    let id_table_start = image.position;
+    image.write(&le64(id_block_start))?;
+    let bytes_used = image.position;
+
+    let mkfs_time = match source_date_epoch {
+        Some(epoch) => epoch,
+        None => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+    };
+    let mut superblock = Vec::with_capacity(SUPERBLOCK_SIZE as usize);
+    superblock.extend_from_slice(&le32(MAGIC));
+    superblock.extend_from_slice(&le32(inode_count));
+    superblock.extend_from_slice(&le32(min(mkfs_time, u32::MAX as u64) as u32));
+    superblock.extend_from_slice(&le32(BLOCK_SIZE));
+    superblock.extend_from_slice(&le32(0)); // Fragment count
+    superblock.extend_from_slice(&le16(COMPRESSION_ZLIB));
+    superblock.extend_from_slice(&le16(BLOCK_LOG));
+    superblock.extend_from_slice(&le16(FLAG_NO_FRAGMENTS | FLAG_NO_XATTRS));
+    superblock.extend_from_slice(&le16(1)); // ID count
+    superblock.extend_from_slice(&le16(VERSION_MAJOR));
+    superblock.extend_from_slice(&le16(VERSION_MINOR));
+    superblock.extend_from_slice(&le64(root_inode));
+    superblock.extend_from_slice(&le64(bytes_used));
+    superblock.extend_from_slice(&le64(id_table_start));
+    superblock.extend_from_slice(&le64(NO_TABLE)); // Extended attribute table
+    superblock.extend_from_slice(&le64(inode_table_start));
+    superblock.extend_from_slice(&le64(directory_table_start));
+    // There are no fragments, but the fragment table nominally starts where
+    // the directory table ends.
+    superblock.extend_from_slice(&le64(id_block_start));
+    superblock.extend_from_slice(&le64(NO_TABLE)); // Export table
+    debug_assert_eq!(superblock.len() as u64, SUPERBLOCK_SIZE);
+    let writer = image.writer;
+    writer.seek(SeekFrom::Start(base))?;
+    writer.write_all(&superblock)?;
+    writer.seek(SeekFrom::Start(base + bytes_used))?;
+    writer.flush()?;
+    Ok(())
+}
+
+// A file or directory to be stored in the image.
+struct Node {
+    // The node's name (empty for the root directory).
+    name: String,
+    // The node's path, relative to the root directory.
+    path: PathBuf,
+    // The node's permission bits.
+    mode: u16,
+    // The node's modification time, in seconds since the Unix epoch.
+    mtime: u32,
+    inode_number: u32,
+    // The directory's entries, sorted by name, or `None` for a file.
+    children: Option<Vec<Node>>,
+}
+
+// Reads the file or directory at `root.join(path)`, and (for a directory)
+// everything beneath it.
+fn read_node<F>(root: &Path, path: PathBuf, is_executable: &F, source_date_epoch: Option<u64>)
+                -> ::Result<Node>
+    where F: Fn(&Path, &fs::Metadata) -> bool
+{
+    let full_path = root.join(&path);
+    let metadata = fs::symlink_metadata(&full_path)?;
+    let name = match path.file_name() {
+        Some(name) => match name.to_str() {
+            Some(name) => name.to_string(),
+            None => bail!("Non-UTF-8 path: {:?}", full_path),
+        },
+        None => String::new(),
+    };
+    let mut mtime = common::modified_time(&metadata);
+    if let Some(epoch) = source_date_epoch {
+        mtime = min(mtime, epoch);
+    }
+    let children = if metadata.is_dir() {
+        let mut children = Vec::new();
+        for entry in fs::read_dir(&full_path)? {
+            let child_path = path.join(entry?.file_name());
+            children.push(read_node(root, child_path, is_executable, source_date_epoch)?);
+        }
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        Some(children)
+    } else if metadata.is_file() {
+        if metadata.len() > u32::MAX as u64 {
+            bail!("{:?} is too large to store in a SquashFS image", full_path);
+        }
+        None
+    } else {
+        bail!("Unsupported file type for {:?} (only files and directories are supported)",
+              full_path);
+    };
+    let mode = if children.is_some() || is_executable(&path, &metadata) { 0o755 } else { 0o644 };
+    Ok(Node {
+        name,
+        path,
+        mode,
+        mtime: min(mtime, u32::MAX as u64) as u32,
+        inode_number: 0,
+        children,
+    })
+}
+
+// Numbers the inodes of everything beneath `dir`.  The entries of each
+// directory get consecutive numbers, since each directory header stores one
+// inode number that its entries' numbers are stored relative to.
+fn assign_inode_numbers(dir: &mut Node, next_inode_number: &mut u32) {
+    if let Some(ref mut children) = dir.children {
+        for child in children.iter_mut() {
+            child.inode_number = *next_inode_number;
+            *next_inode_number += 1;
+        }
+        for child in children.iter_mut() {
+            assign_inode_numbers(child, next_inode_number);
+        }
+    }
+}
+
+// The location of an inode or directory listing within its table: the
+// position of its metadata block within the table, and its offset within
+// that block once uncompressed.
+#[derive(Clone, Copy)]
+struct MetadataRef {
+    block: u32,
+    offset: u16,
+}
+
+impl MetadataRef {
+    // Returns the 64-bit form that inode references are stored in.
+    fn to_inode_ref(self) -> u64 {
+        (self.block as u64) << 16 | self.offset as u64
+    }
+}
+
+// Accumulates a table of metadata blocks in memory.
+struct MetadataWriter {
+    blocks: Vec<u8>,
+    // The (uncompressed) data for the block currently being written.
+    pending: Vec<u8>,
+}
+
+impl MetadataWriter {
+    fn new() -> MetadataWriter {
+        MetadataWriter {
+            blocks: Vec::new(),
+            pending: Vec::with_capacity(METADATA_BLOCK_SIZE),
+        }
+    }
+
+    // Returns the location that the next data written will be at.
+    fn position(&self) -> ::Result<MetadataRef> {
+        if self.blocks.len() > u32::MAX as usize {
+            bail!("SquashFS metadata table is too large");
+        }
+        Ok(MetadataRef {
+            block: self.blocks.len() as u32,
+            offset: self.pending.len() as u16,
+        })
+    }
+
+    // Writes data to the table, which may span more than one block.
+    fn write(&mut self, mut data: &[u8]) -> ::Result<()> {
+        while !data.is_empty() {
+            let size = min(data.len(), METADATA_BLOCK_SIZE - self.pending.len());
+            self.pending.extend_from_slice(&data[..size]);
+            data = &data[size..];
+            if self.pending.len() == METADATA_BLOCK_SIZE {
+                self.flush_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> ::Result<()> {
+        match compress(&self.pending)? {
+            Some(compressed) => {
+                self.blocks.extend_from_slice(&le16(compressed.len() as u16));
+                self.blocks.extend_from_slice(&compressed);
+            }
+            None => {
+                self.blocks.extend_from_slice(
+                    &le16(self.pending.len() as u16 | METADATA_UNCOMPRESSED));
+                self.blocks.extend_from_slice(&self.pending);
+            }
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    // Writes out the final, partial block (if any), and returns the table.
+    fn finish(mut self) -> ::Result<Vec<u8>> {
+        if !self.pending.is_empty() {
+            self.flush_block()?;
+        }
+        Ok(self.blocks)
+    }
+}
+
+// An entry in a directory listing.
+struct DirectoryEntry<'a> {
+    name: &'a str,
+    inode: MetadataRef,
+    inode_number: u32,
+    inode_type: u16,
+}
+
+struct ImageWriter<'a, W: 'a> {
+    writer: &'a mut W,
+    // The current position within the image.
+    position: u64,
+    inodes: MetadataWriter,
+    directories: MetadataWriter,
+}
+
+impl<'a, W: Write + Seek> ImageWriter<'a, W> {
+    fn write(&mut self, data: &[u8]) -> ::Result<()> {
+        self.writer.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    // Writes the inode for `node` (along with its data, or for a directory,
+    // everything beneath it), and returns the inode's location.
+    fn write_node(&mut self, root: &Path, node: &Node, parent_inode_number: u32)
+                  -> ::Result<MetadataRef> {
+        let mut inode = Vec::new();
+        match node.children {
+            Some(ref children) => {
+                let mut entries = Vec::with_capacity(children.len());
+                for child in children.iter() {
+                    let child_inode = self.write_node(root, child, node.inode_number)?;
+                    entries.push(DirectoryEntry {
+                        name: &child.name,
+                        inode: child_inode,
+                        inode_number: child.inode_number,
+                        inode_type: if child.children.is_some() {
+                            INODE_TYPE_DIRECTORY
+                        } else {
+                            INODE_TYPE_FILE
+                        },
+                    });
+                }
+                let listing_position = self.directories.position()?;
+                let listing = directory_listing(&entries)?;
+                self.directories.write(&listing)?;
+                // The listing size includes the implicit "." and ".." entries.
+                let listing_size = listing.len() + 3;
+                if listing_size > u16::MAX as usize {
+                    bail!("Directory {:?} has too many entries", node.path);
+                }
+                let subdirectories = children.iter().filter(|c| c.children.is_some()).count();
+                inode.extend_from_slice(&inode_header(INODE_TYPE_DIRECTORY, node));
+                inode.extend_from_slice(&le32(listing_position.block));
+                inode.extend_from_slice(&le32(2 + subdirectories as u32)); // Link count
+                inode.extend_from_slice(&le16(listing_size as u16));
+                inode.extend_from_slice(&le16(listing_position.offset));
+                inode.extend_from_slice(&le32(parent_inode_number));
+            }
+            None => {
+                let blocks_start = self.position;
+                if blocks_start > u32::MAX as u64 {
+                    bail!("SquashFS image is too large");
+                }
+                let mut file = File::open(root.join(&node.path))?;
+                let mut block_sizes = Vec::new();
+                let mut file_size: u64 = 0;
+                let mut buffer = vec![0; BLOCK_SIZE as usize];
+                loop {
+                    let size = read_block(&mut file, &mut buffer)?;
+                    if size == 0 {
+                        break;
+                    }
+                    file_size += size as u64;
+                    match compress(&buffer[..size])? {
+                        Some(compressed) => {
+                            self.write(&compressed)?;
+                            block_sizes.push(compressed.len() as u32);
+                        }
+                        None => {
+                            self.write(&buffer[..size])?;
+                            block_sizes.push(size as u32 | DATA_UNCOMPRESSED);
+                        }
+                    }
+                }
+                if file_size > u32::MAX as u64 {
+                    bail!("{:?} is too large to store in a SquashFS image", node.path);
+                }
+                inode.extend_from_slice(&inode_header(INODE_TYPE_FILE, node));
+                inode.extend_from_slice(&le32(blocks_start as u32));
+                inode.extend_from_slice(&le32(NO_FRAGMENT));
+                inode.extend_from_slice(&le32(0)); // Offset within fragment
+                inode.extend_from_slice(&le32(file_size as u32));
+                for size in block_sizes {
+                    inode.extend_from_slice(&le32(size));
+                }
+            }
+        }
+        let position = self.inodes.position()?;
+        self.inodes.write(&inode)?;
+        Ok(position)
+    }
+}
+
+// Returns the header that every inode starts with.
+fn inode_header(inode_type: u16, node: &Node) -> Vec<u8> {
+    let mut header = Vec::with_capacity(16);
+    header.extend_from_slice(&le16(inode_type));
+    header.extend_from_slice(&le16(node.mode));
+    header.extend_from_slice(&le16(0)); // Index of owner's user ID
+    header.extend_from_slice(&le16(0)); // Index of owner's group ID
+    header.extend_from_slice(&le32(node.mtime));
+    header.extend_from_slice(&le32(node.inode_number));
+    header
+}
+
+// Encodes a directory listing.  Entries are grouped under headers, each of
+// which covers up to 256 entries whose inodes are in the same metadata block.
+fn directory_listing(entries: &[DirectoryEntry]) -> ::Result<Vec<u8>> {
+    let mut listing = Vec::new();
+    let mut index = 0;
+    while index < entries.len() {
+        let first = &entries[index];
+        let count = entries[index..].iter()
+            .take(DIRECTORY_HEADER_MAX_ENTRIES)
+            .take_while(|entry| entry.inode.block == first.inode.block)
+            .count();
+        listing.extend_from_slice(&le32(count as u32 - 1));
+        listing.extend_from_slice(&le32(first.inode.block));
+        listing.extend_from_slice(&le32(first.inode_number));
+        for entry in entries[index..index + count].iter() {
+            if entry.name.is_empty() || entry.name.len() > 256 {
+                bail!("Invalid file name for a SquashFS image: {:?}", entry.name);
+            }
+            // Entries within a directory have consecutive inode numbers (see
+            // `assign_inode_numbers`), so the difference always fits.
+            let inode_number_delta = entry.inode_number as i32 - first.inode_number as i32;
+            listing.extend_from_slice(&le16(entry.inode.offset));
+            listing.extend_from_slice(&le16(inode_number_delta as i16 as u16));
+            listing.extend_from_slice(&le16(entry.inode_type));
+            listing.extend_from_slice(&le16(entry.name.len() as u16 - 1));
+            listing.extend_from_slice(entry.name.as_bytes());
+        }
+        index += count;
+    }
+    Ok(listing)
+}
+
+// Fills as much of `buffer` as possible from `file`, returning the number of
+// bytes read (which is less than the buffer size only at the end of the file).
+fn read_block(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut size = 0;
+    while size < buffer.len() {
+        match file.read(&mut buffer[size..])? {
+            0 => break,
+            count => size += count,
+        }
+    }
+    Ok(size)
+}
+
+// Compresses a block with zlib, returning `None` if that wouldn't make it
+// any smaller.
+fn compress(data: &[u8]) -> ::Result<Option<Vec<u8>>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+    Ok(if compressed.len() < data.len() { Some(compressed) } else { None })
+}
+
+fn le16(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn le32(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn le64(value: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    bytes[..4].copy_from_slice(&le32(value as u32));
+    bytes[4..].copy_from_slice(&le32((value >> 32) as u32));
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_squashfs;
+    use flate2::read::ZlibDecoder;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::{Cursor, Read};
+    use tempfile;
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        data[offset] as u16 | (data[offset + 1] as u16) << 8
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        read_u16(data, offset) as u32 | (read_u16(data, offset + 2) as u32) << 16
+    }
+
+    fn read_u64(data: &[u8], offset: usize) -> u64 {
+        read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+    }
+
+    // Decodes a table of metadata blocks, returning its uncompressed contents
+    // and a map from each block's position to its position once uncompressed.
+    fn read_metadata(table: &[u8]) -> (Vec<u8>, HashMap<u32, usize>) {
+        let mut data = Vec::new();
+        let mut positions = HashMap::new();
+        let mut offset = 0;
+        while offset < table.len() {
+            positions.insert(offset as u32, data.len());
+            let header = read_u16(table, offset);
+            let size = (header & 0x7fff) as usize;
+            let block = &table[offset + 2..offset + 2 + size];
+            if header & 0x8000 != 0 {
+                data.extend_from_slice(block);
+            } else {
+                ZlibDecoder::new(block).read_to_end(&mut data).unwrap();
+            }
+            offset += 2 + size;
+        }
+        (data, positions)
+    }
+
+    #[test]
+    fn writes_directory_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("usr/bin")).unwrap();
+        fs::write(dir.path().join("usr/bin/app"), b"#!/bin/sh\n").unwrap();
+        fs::write(dir.path().join("big.dat"), vec![7u8; 300_000]).unwrap();
+        let mut cursor = Cursor::new(b"RUNTIME".to_vec());
+        cursor.set_position(7);
+        write_squashfs(dir.path(), &mut cursor, |path, _| path.starts_with("usr/bin"),
+                       Some(1000)).unwrap();
+        let data = cursor.into_inner();
+        assert_eq!(&data[..7], b"RUNTIME");
+        let image = &data[7..];
+
+        // Check the superblock.
+        assert_eq!(read_u32(image, 0), 0x73717368);
+        assert_eq!(read_u32(image, 4), 5);
+        assert_eq!(read_u32(image, 8), 1000);
+        assert_eq!(read_u64(image, 40) as usize, image.len());
+        let inode_table_start = read_u64(image, 64) as usize;
+        let directory_table_start = read_u64(image, 72) as usize;
+        let id_block_start = read_u64(image, 80) as usize;
+        assert_eq!(read_u64(image, 48) as usize, image.len() - 8);
+        assert_eq!(read_u64(image, image.len() - 8) as usize, id_block_start);
+        let (inodes, inode_blocks) = read_metadata(&image[inode_table_start..
+                                                          directory_table_start]);
+        let (listings, listing_blocks) = read_metadata(&image[directory_table_start..
+                                                              id_block_start]);
+        let inode_at = |block: u32, offset: u16| inode_blocks[&block] + offset as usize;
+
+        // Read the root directory's listing.
+        let root_ref = read_u64(image, 32);
+        let root = inode_at((root_ref >> 16) as u32, root_ref as u16);
+        assert_eq!(read_u16(&inodes, root), 1);
+        assert_eq!(read_u16(&inodes, root + 2), 0o755);
+        assert_eq!(read_u32(&inodes, root + 20), 3);
+        let listing_size = read_u16(&inodes, root + 24) as usize - 3;
+        let listing = listing_blocks[&read_u32(&inodes, root + 16)] +
+            read_u16(&inodes, root + 26) as usize;
+        let listing = &listings[listing..listing + listing_size];
+        assert_eq!(read_u32(listing, 0), 1);
+        let inode_block = read_u32(listing, 4);
+        let mut names = Vec::new();
+        let mut offset = 12;
+        while offset < listing.len() {
+            let name_size = read_u16(listing, offset + 6) as usize + 1;
+            let name = &listing[offset + 8..offset + 8 + name_size];
+            names.push((String::from_utf8(name.to_vec()).unwrap(),
+                        inode_at(inode_block, read_u16(listing, offset)),
+                        read_u16(listing, offset + 4)));
+            offset += 8 + name_size;
+        }
+        assert_eq!(names.iter().map(|n| n.0.as_str()).collect::<Vec<_>>(), vec!["big.dat", "usr"]);
+        assert_eq!(names[0].2, 2);
+        assert_eq!(names[1].2, 1);
+
+        // Check the contents of big.dat, which takes three blocks.
+        let file = names[0].1;
+        assert_eq!(read_u16(&inodes, file), 2);
+        assert_eq!(read_u16(&inodes, file + 2), 0o644);
+        assert_eq!(read_u32(&inodes, file + 20), 0xffffffff);
+        assert_eq!(read_u32(&inodes, file + 28), 300_000);
+        let mut position = read_u32(&inodes, file + 16) as usize;
+        let mut contents = Vec::new();
+        for block in 0..3 {
+            let size = read_u32(&inodes, file + 32 + 4 * block);
+            assert_eq!(size & (1 << 24), 0);
+            let size = size as usize;
+            ZlibDecoder::new(&image[position..position + size]).read_to_end(&mut contents)
+                .unwrap();
+            position += size;
+        }
+        assert_eq!(contents, vec![7u8; 300_000]);
+    }
+}
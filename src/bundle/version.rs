@@ -0,0 +1,166 @@
+use super::common;
+use std::fmt;
+
+/// An app version, in the semver format that Cargo uses
+/// (`MAJOR.MINOR.PATCH`, optionally followed by `-PRERELEASE` and/or
+/// `+BUILD`), which can be translated into the version format of each kind of
+/// package.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Version {
+    major: u64,
+    minor: u64,
+    patch: u64,
+    pre: Option<String>,
+    build: Option<String>,
+}
+
+impl Version {
+    pub fn parse(string: &str) -> ::Result<Version> {
+        let (rest, build) = match string.find('+') {
+            Some(index) => (&string[..index], Some(&string[index + 1..])),
+            None => (string, None),
+        };
+        let (core, pre) = match rest.find('-') {
+            Some(index) => (&rest[..index], Some(&rest[index + 1..])),
+            None => (rest, None),
+        };
+        let numbers: Vec<Option<u64>> = core.split('.').map(parse_number).collect();
+        if numbers.len() != 3 || numbers.contains(&None) ||
+            !pre.is_none_or(is_valid_metadata) || !build.is_none_or(is_valid_metadata)
+        {
+            bail!("Invalid version {:?} (versions must have the form MAJOR.MINOR.PATCH, \
+                   optionally followed by -PRERELEASE and/or +BUILD)", string);
+        }
+        Ok(Version {
+            major: numbers[0].unwrap(),
+            minor: numbers[1].unwrap(),
+            patch: numbers[2].unwrap(),
+            pre: pre.map(str::to_string),
+            build: build.map(str::to_string),
+        })
+    }
+
+    /// Returns the version in the form used for the ProductVersion of an MSI
+    /// package, which must be `major.minor.build`, with limits of
+    /// 255.255.65535.  Any pre-release or build metadata is dropped (with a
+    /// warning), since Windows Installer has no way to represent it.
+    pub fn msi_version(&self) -> ::Result<String> {
+        if self.major > 255 || self.minor > 255 || self.patch > 65535 {
+            bail!("Version {} can't be used for an MSI package, as MSI versions are limited \
+                   to 255.255.65535", self);
+        }
+        self.warn_if_truncated("MSI packages")?;
+        Ok(self.numeric_version())
+    }
+
+    /// Returns the version in the form used for Debian and RPM packages.
+    /// Both formats sort a `~` before anything else, even the end of the
+    /// version, so a pre-release such as `1.0.0-beta.1` becomes
+    /// `1.0.0~beta.1`, which sorts before `1.0.0` just as in semver.  Build
+    /// metadata is kept after a `+`.  Hyphens within the pre-release or build
+    /// metadata are replaced with periods, since both formats use a hyphen to
+    /// separate the package revision (or release) from the version.
+    pub fn linux_version(&self) -> String {
+        let mut version = self.numeric_version();
+        if let Some(ref pre) = self.pre {
+            version.push('~');
+            version.push_str(&pre.replace('-', "."));
+        }
+        if let Some(ref build) = self.build {
+            version.push('+');
+            version.push_str(&build.replace('-', "."));
+        }
+        version
+    }
+
+    /// Returns the version in the form used for the CFBundleShortVersionString
+    /// of macOS and iOS apps, which must be three period-separated integers.
+    /// Any pre-release or build metadata is dropped (with a warning).
+    pub fn short_version_string(&self) -> ::Result<String> {
+        self.warn_if_truncated("macOS and iOS bundles")?;
+        Ok(self.numeric_version())
+    }
+
+    fn numeric_version(&self) -> String {
+        format!("{}.{}.{}", self.major, self.minor, self.patch)
+    }
+
+    fn warn_if_truncated(&self, packages: &str) -> ::Result<()> {
+        if self.pre.is_some() || self.build.is_some() {
+            common::print_warning(&format!("{} can't have pre-release or build versions, so \
+                                            version {} will be packaged as {}",
+                                           packages, self, self.numeric_version()))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.numeric_version())?;
+        if let Some(ref pre) = self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        if let Some(ref build) = self.build {
+            write!(f, "+{}", build)?;
+        }
+        Ok(())
+    }
+}
+
+// Parses a numeric version component, which may not have leading zeros.
+fn parse_number(string: &str) -> Option<u64> {
+    if string.is_empty() || (string.len() > 1 && string.starts_with('0')) ||
+        !string.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    string.parse().ok()
+}
+
+// Returns true if `string` is valid pre-release or build metadata: a list of
+// non-empty, period-separated identifiers made up of ASCII alphanumerics and
+// hyphens.
+fn is_valid_metadata(string: &str) -> bool {
+    string.split('.').all(|identifier| {
+        !identifier.is_empty() &&
+            identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Version;
+
+    #[test]
+    fn parse_versions() {
+        let version = Version::parse("1.2.3-beta.1+abc-def").unwrap();
+        assert_eq!(version.major, 1);
+        assert_eq!(version.minor, 2);
+        assert_eq!(version.patch, 3);
+        assert_eq!(version.pre, Some("beta.1".to_string()));
+        assert_eq!(version.build, Some("abc-def".to_string()));
+        assert_eq!(version.to_string(), "1.2.3-beta.1+abc-def");
+        assert_eq!(Version::parse("0.10.0").unwrap().to_string(), "0.10.0");
+        for invalid in &["1.2", "1.2.3.4", "1.02.3", "1.2.x", "1.2.3-", "1.2.3-a..b", "1.2.3+a_b"] {
+            assert!(Version::parse(invalid).is_err(), "{} should be invalid", invalid);
+        }
+    }
+
+    #[test]
+    fn msi_versions() {
+        assert_eq!(Version::parse("255.255.65535").unwrap().msi_version().unwrap(),
+                   "255.255.65535");
+        assert_eq!(Version::parse("1.2.3-beta.1+abc").unwrap().msi_version().unwrap(), "1.2.3");
+        assert!(Version::parse("256.0.0").unwrap().msi_version().is_err());
+        assert!(Version::parse("1.256.0").unwrap().msi_version().is_err());
+        assert!(Version::parse("1.2.65536").unwrap().msi_version().is_err());
+    }
+
+    #[test]
+    fn linux_versions() {
+        assert_eq!(Version::parse("1.2.3").unwrap().linux_version(), "1.2.3");
+        assert_eq!(Version::parse("1.2.3-rc-1+build-5").unwrap().linux_version(),
+                   "1.2.3~rc.1+build.5");
+    }
+}
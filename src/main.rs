@@ -1,34 +1,40 @@
 extern crate ar;
 extern crate cab;
+extern crate cfb;
 extern crate chrono;
 #[macro_use]
 extern crate clap;
 extern crate dirs;
 #[macro_use]
 extern crate error_chain;
+extern crate flate2;
 extern crate glob;
 extern crate icns;
 extern crate image;
-extern crate libflate;
 extern crate md5;
 extern crate msi;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate sha2;
 extern crate strsim;
 extern crate tar;
 extern crate target_build_utils;
 extern crate term;
+extern crate time;
 extern crate toml;
 extern crate uuid;
 extern crate walkdir;
+extern crate xz2;
+extern crate zip;
+extern crate zstd;
 
 #[cfg(test)]
 extern crate tempfile;
 
 mod bundle;
 
-use bundle::{BuildArtifact, PackageType, Settings, bundle_project};
+use bundle::{BuildArtifact, DebCompression, PackageType, Settings, bundle_project};
 use clap::{App, AppSettings, Arg, SubCommand};
 use std::env;
 use std::process;
@@ -43,6 +49,7 @@ error_chain! {
         Term(::term::Error);
         Toml(::toml::de::Error);
         Walkdir(::walkdir::Error);
+        Zip(::zip::result::ZipError);
     }
     errors { }
 }
@@ -97,6 +104,8 @@ fn build_project_if_unbuilt(settings: &Settings) -> ::Result<()> {
 fn run() -> ::Result<()> {
     let all_formats: Vec<&str> =
         PackageType::all().iter().map(PackageType::short_name).collect();
+    let deb_compressions: Vec<&str> =
+        DebCompression::all().iter().map(DebCompression::short_name).collect();
     let m = App::new("cargo-bundle")
         .version(format!("v{}", crate_version!()).as_str())
         .bin_name("cargo")
@@ -142,7 +151,20 @@ fn run() -> ::Result<()> {
                          .help("Build a bundle with all crate features."))
                     .arg(Arg::with_name("no-default-features")
                          .long("no-default-features")
-                         .help("Build a bundle without the default crate features.")))
+                         .help("Build a bundle without the default crate features."))
+                    .arg(Arg::with_name("deb-compression")
+                         .long("deb-compression")
+                         .value_name("CODEC")
+                         .possible_values(&deb_compressions)
+                         .help("How to compress the members of deb packages (default: gzip)"))
+                    .arg(Arg::with_name("deb-compression-level")
+                         .long("deb-compression-level")
+                         .value_name("LEVEL")
+                         .help("The compression level to use for the members of deb packages"))
+                    .arg(Arg::with_name("reproducible")
+                         .long("reproducible")
+                         .help("Produce byte-identical bundles from identical inputs \
+                                (implied when SOURCE_DATE_EPOCH is set)")))
 
         .get_matches();
 
@@ -0,0 +1,63 @@
+extern crate md5;
+extern crate tempfile;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+const CARGO_TOML: &str = r#"
+[package]
+name = "example"
+version = "0.1.0"
+authors = ["Jane Doe <jane@example.com>"]
+description = "An example application."
+
+[package.metadata.bundle]
+identifier = "com.example.example"
+resources = ["assets"]
+"#;
+
+fn write_project_files(project_dir: &Path) {
+    fs::write(project_dir.join("Cargo.toml"), CARGO_TOML).unwrap();
+    fs::create_dir_all(project_dir.join("assets/sub")).unwrap();
+    fs::write(project_dir.join("assets/a.txt"), "a").unwrap();
+    fs::write(project_dir.join("assets/sub/b.txt"), "b").unwrap();
+    fs::create_dir_all(project_dir.join("target/debug")).unwrap();
+    fs::write(project_dir.join("target/debug/example"), "not really a binary").unwrap();
+}
+
+/// Bundles the project in the given format and returns the MD5 digest of the
+/// resulting package file.
+fn bundle(project_dir: &Path, format: &str) -> md5::Digest {
+    let status = Command::new(env!("CARGO_BIN_EXE_cargo-bundle"))
+        .args(["bundle", "--reproducible", "--format", format])
+        .current_dir(project_dir)
+        .env("CARGO_BUNDLE_SKIP_BUILD", "1")
+        .env_remove("SOURCE_DATE_EPOCH")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let bundle_dir = project_dir.join("target/debug/bundle").join(format);
+    let package_path = fs::read_dir(&bundle_dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension() == Some(OsStr::new(format)))
+        .unwrap();
+    md5::compute(fs::read(package_path).unwrap())
+}
+
+#[test]
+fn reproducible_bundles_are_identical() {
+    let project_dir = tempfile::tempdir().unwrap();
+    for format in &["deb", "rpm", "msi"] {
+        write_project_files(project_dir.path());
+        let first = bundle(project_dir.path(), format);
+        // Rewrite every input file, so that their modification times change.
+        thread::sleep(Duration::from_millis(1100));
+        write_project_files(project_dir.path());
+        let second = bundle(project_dir.path(), format);
+        assert_eq!(first, second, "{} bundles differ", format);
+    }
+}
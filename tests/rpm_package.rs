@@ -0,0 +1,130 @@
+extern crate md5;
+extern crate tempfile;
+
+use std::collections::HashMap;
+use std::fs;
+use std::process::Command;
+
+const CARGO_TOML: &str = r#"
+[package]
+name = "example"
+version = "0.1.0"
+authors = ["Jane Doe <jane@example.com>"]
+description = "An example application."
+
+[package.metadata.bundle]
+identifier = "com.example.example"
+resources = ["assets"]
+"#;
+
+// Header entry types that the test reads back:
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_BIN: u32 = 7;
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// A header structure parsed from an RPM file, mapping each tag to its type,
+/// count and data (which runs to the end of the header's data store).
+struct Header<'a> {
+    entries: HashMap<u32, (u32, u32, &'a [u8])>,
+    len: usize,
+}
+
+impl<'a> Header<'a> {
+    fn parse(bytes: &'a [u8]) -> Header<'a> {
+        assert_eq!(&bytes[..8], &[0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0]);
+        let num_entries = read_u32(bytes, 8) as usize;
+        let store_size = read_u32(bytes, 12) as usize;
+        let store_start = 16 + 16 * num_entries;
+        let store = &bytes[store_start..store_start + store_size];
+        let mut entries = HashMap::new();
+        for index in 0..num_entries {
+            let entry = 16 + 16 * index;
+            let offset = read_u32(bytes, entry + 8) as usize;
+            entries.insert(read_u32(bytes, entry),
+                           (read_u32(bytes, entry + 4), read_u32(bytes, entry + 12),
+                            &store[offset..]));
+        }
+        Header { entries, len: store_start + store_size }
+    }
+
+    fn string(&self, tag: u32) -> &str {
+        let &(kind, count, data) = self.entries.get(&tag)
+            .unwrap_or_else(|| panic!("missing tag {}", tag));
+        assert_eq!((kind, count), (TYPE_STRING, 1), "tag {} should be a string", tag);
+        let end = data.iter().position(|&byte| byte == 0).unwrap();
+        std::str::from_utf8(&data[..end]).unwrap()
+    }
+
+    fn int32(&self, tag: u32) -> u32 {
+        let &(kind, count, data) = self.entries.get(&tag)
+            .unwrap_or_else(|| panic!("missing tag {}", tag));
+        assert_eq!((kind, count), (TYPE_INT32, 1), "tag {} should be an int32", tag);
+        read_u32(data, 0)
+    }
+
+    fn binary(&self, tag: u32) -> &[u8] {
+        let &(kind, count, data) = self.entries.get(&tag)
+            .unwrap_or_else(|| panic!("missing tag {}", tag));
+        assert_eq!(kind, TYPE_BIN, "tag {} should be binary", tag);
+        &data[..count as usize]
+    }
+}
+
+#[test]
+fn rpm_package_can_be_parsed() {
+    let project_dir = tempfile::tempdir().unwrap();
+    let project_dir = project_dir.path();
+    fs::write(project_dir.join("Cargo.toml"), CARGO_TOML).unwrap();
+    fs::create_dir_all(project_dir.join("assets")).unwrap();
+    fs::write(project_dir.join("assets/a.txt"), "a").unwrap();
+    fs::create_dir_all(project_dir.join("target/debug")).unwrap();
+    fs::write(project_dir.join("target/debug/example"), "not really a binary").unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_cargo-bundle"))
+        .args(["bundle", "--format", "rpm"])
+        .current_dir(project_dir)
+        .env("CARGO_BUNDLE_SKIP_BUILD", "1")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let bundle_dir = project_dir.join("target/debug/bundle/rpm");
+    let package_path = fs::read_dir(&bundle_dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().and_then(|ext| ext.to_str()) == Some("rpm"))
+        .unwrap();
+    let bytes = fs::read(&package_path).unwrap();
+    let file_name = package_path.file_name().unwrap().to_str().unwrap();
+    let arch = file_name.trim_start_matches("example-0.1.0-1.").trim_end_matches(".rpm");
+
+    // The lead: magic, format version 3.0, a binary package, and its name.
+    assert_eq!(&bytes[..4], &[0xed, 0xab, 0xee, 0xdb]);
+    assert_eq!(&bytes[4..6], &[3, 0]);
+    assert_eq!(read_u16(&bytes, 6), 0);
+    assert!(bytes[10..76].starts_with(b"example-0.1.0-1\0"));
+    assert_eq!(read_u16(&bytes, 78), 5);
+
+    // The signature, padded so that the main header is 8-byte aligned.
+    let signature = Header::parse(&bytes[96..]);
+    let header_start = 96 + signature.len.div_ceil(8) * 8;
+    let header = Header::parse(&bytes[header_start..]);
+    let rest = &bytes[header_start..];
+    assert_eq!(signature.int32(1000) as usize, rest.len());
+    assert_eq!(signature.binary(1004), &md5::compute(rest)[..]);
+
+    // The main header, followed by a gzip-compressed payload.
+    assert_eq!(header.string(1000), "example");
+    assert_eq!(header.string(1001), "0.1.0");
+    assert_eq!(header.string(1002), "1");
+    assert_eq!(header.string(1021), "linux");
+    assert_eq!(header.string(1022), arch);
+    assert_eq!(header.string(1044), "example-0.1.0-1.src.rpm");
+    assert_eq!(header.string(1124), "cpio");
+    assert_eq!(&rest[header.len..header.len + 2], &[0x1f, 0x8b]);
+}